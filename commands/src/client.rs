@@ -1,5 +1,5 @@
 use crate::proto::messages::horust_msg_message::MessageType;
-use crate::proto::messages::{horust_msg_request, horust_msg_response, HorustMsgMessage, HorustMsgRequest, HorustMsgServiceChangeRequest, HorustMsgServiceInfoRequest, HorustMsgServiceStatusRequest};
+use crate::proto::messages::{horust_msg_request, horust_msg_response, HorustMsgMessage, HorustMsgRequest, HorustMsgServiceChangeRequest, HorustMsgServiceConfigRequest, HorustMsgServiceInfoRequest, HorustMsgServiceReloadRequest, HorustMsgServiceStatusRequest};
 use crate::{HorustChangeServiceStatus, HorustMsgServiceStatus, UdsConnectionHandler};
 use anyhow::{anyhow, Context};
 use anyhow::{bail, Result};
@@ -28,6 +28,8 @@ fn unwrap_response(response: HorustMsgMessage) -> Option<Result<horust_msg_respo
             horust_msg_response::Response::StatusResponse(_status) => Some(Ok(v)),
             horust_msg_response::Response::InfoResponse(_status) => Some(Ok(v)),
             horust_msg_response::Response::ChangeResponse(_status) => Some(Ok(v)),
+            horust_msg_response::Response::ConfigResponse(_config) => Some(Ok(v)),
+            horust_msg_response::Response::ReloadResponse(_reload) => Some(Ok(v)),
         };
     }
     None
@@ -45,10 +47,11 @@ impl ClientHandler {
         })
     }
 
+    /// Returns the service name, its status and the reason of the status, if known.
     pub fn send_status_request(
         &mut self,
         service_name: String,
-    ) -> Result<(String, HorustMsgServiceStatus)> {
+    ) -> Result<(String, HorustMsgServiceStatus, Option<String>)> {
         let status = new_request(horust_msg_request::Request::StatusRequest(
             HorustMsgServiceStatusRequest { service_name },
         ));
@@ -65,6 +68,7 @@ impl ClientHandler {
             Ok((
                 resp.service_name,
                 HorustMsgServiceStatus::try_from(resp.service_status).unwrap(),
+                Some(resp.reason).filter(|reason| !reason.is_empty()),
             ))
         } else {
             bail!("Invalid response received: {:?}", response);
@@ -97,6 +101,47 @@ impl ClientHandler {
         }
     }
 
+    pub fn send_config_request(&mut self, service_name: String) -> Result<(String, String)> {
+        let config = new_request(horust_msg_request::Request::ConfigRequest(
+            HorustMsgServiceConfigRequest { service_name },
+        ));
+        self.uds_connection_handler.send_message(config)?;
+        // server is waiting for EOF.
+        self.uds_connection_handler
+            .socket
+            .shutdown(Shutdown::Write)?;
+        //Reads all bytes until EOF in this source, appending them to buf.
+        let received = self.uds_connection_handler.receive_message()?;
+        debug!("Client: received: {received:?}");
+        let response = unwrap_response(received).unwrap()?;
+        if let horust_msg_response::Response::ConfigResponse(resp) = response {
+            Ok((resp.service_name, resp.config))
+        } else {
+            bail!("Invalid response received: {:?}", response);
+        }
+    }
+
+    /// Returns once the reload has been requested, the service is reloaded asynchronously.
+    pub fn send_reload_request(&mut self, service_name: String) -> Result<String> {
+        let reload = new_request(horust_msg_request::Request::ReloadRequest(
+            HorustMsgServiceReloadRequest { service_name },
+        ));
+        self.uds_connection_handler.send_message(reload)?;
+        // server is waiting for EOF.
+        self.uds_connection_handler
+            .socket
+            .shutdown(Shutdown::Write)?;
+        //Reads all bytes until EOF in this source, appending them to buf.
+        let received = self.uds_connection_handler.receive_message()?;
+        debug!("Client: received: {received:?}");
+        let response = unwrap_response(received).unwrap()?;
+        if let horust_msg_response::Response::ReloadResponse(resp) = response {
+            Ok(resp.service_name)
+        } else {
+            bail!("Invalid response received: {:?}", response);
+        }
+    }
+
     pub fn send_change_request(
         &mut self,
         service_name: String,
@@ -16,7 +16,7 @@ pub mod horust_msg_message {
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct HorustMsgRequest {
-    #[prost(oneof = "horust_msg_request::Request", tags = "1, 2, 3")]
+    #[prost(oneof = "horust_msg_request::Request", tags = "1, 2, 3, 4, 5")]
     pub request: ::core::option::Option<horust_msg_request::Request>,
 }
 /// Nested message and enum types in `HorustMsgRequest`.
@@ -29,11 +29,15 @@ pub mod horust_msg_request {
         ChangeRequest(super::HorustMsgServiceChangeRequest),
         #[prost(message, tag = "3")]
         InfoRequest(super::HorustMsgServiceInfoRequest),
+        #[prost(message, tag = "4")]
+        ConfigRequest(super::HorustMsgServiceConfigRequest),
+        #[prost(message, tag = "5")]
+        ReloadRequest(super::HorustMsgServiceReloadRequest),
     }
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct HorustMsgResponse {
-    #[prost(oneof = "horust_msg_response::Response", tags = "1, 2, 3, 4")]
+    #[prost(oneof = "horust_msg_response::Response", tags = "1, 2, 3, 4, 5, 6")]
     pub response: ::core::option::Option<horust_msg_response::Response>,
 }
 /// Nested message and enum types in `HorustMsgResponse`.
@@ -48,6 +52,10 @@ pub mod horust_msg_response {
         InfoResponse(super::HorustMsgServiceInfoResponse),
         #[prost(message, tag = "4")]
         ChangeResponse(super::HorustMsgServiceChangeResponse),
+        #[prost(message, tag = "5")]
+        ConfigResponse(super::HorustMsgServiceConfigResponse),
+        #[prost(message, tag = "6")]
+        ReloadResponse(super::HorustMsgServiceReloadResponse),
     }
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
@@ -66,6 +74,9 @@ pub struct HorustMsgServiceStatusResponse {
     pub service_name: ::prost::alloc::string::String,
     #[prost(enumeration = "HorustMsgServiceStatus", tag = "2")]
     pub service_status: i32,
+    /// why the service is in this status, if known. E.g. why it could not be spawned, or how it exited.
+    #[prost(string, tag = "3")]
+    pub reason: ::prost::alloc::string::String,
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct HorustMsgServiceChangeRequest {
@@ -86,6 +97,30 @@ pub struct HorustMsgServiceInfoResponse {
     #[prost(string, tag = "2")]
     pub info: ::prost::alloc::string::String,
 }
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct HorustMsgServiceConfigRequest {
+    #[prost(string, tag = "1")]
+    pub service_name: ::prost::alloc::string::String,
+}
+/// the effective configuration of the service, in toml format.
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct HorustMsgServiceConfigResponse {
+    #[prost(string, tag = "1")]
+    pub service_name: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub config: ::prost::alloc::string::String,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct HorustMsgServiceReloadRequest {
+    #[prost(string, tag = "1")]
+    pub service_name: ::prost::alloc::string::String,
+}
+/// the reload has been requested: the service is reloaded asynchronously and keeps running.
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct HorustMsgServiceReloadResponse {
+    #[prost(string, tag = "1")]
+    pub service_name: ::prost::alloc::string::String,
+}
 /// return the current status - similar to HorustServiceStatusResponse.
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct HorustMsgServiceChangeResponse {
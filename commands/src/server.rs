@@ -1,5 +1,5 @@
 use crate::proto::messages::horust_msg_message::MessageType::Request;
-use crate::proto::messages::{horust_msg_message, horust_msg_request, horust_msg_response, HorustChangeServiceStatus, HorustMsgError, HorustMsgMessage, HorustMsgRequest, HorustMsgResponse, HorustMsgServiceChangeResponse, HorustMsgServiceInfoResponse, HorustMsgServiceStatus, HorustMsgServiceStatusResponse};
+use crate::proto::messages::{horust_msg_message, horust_msg_request, horust_msg_response, HorustChangeServiceStatus, HorustMsgError, HorustMsgMessage, HorustMsgRequest, HorustMsgResponse, HorustMsgServiceChangeResponse, HorustMsgServiceConfigResponse, HorustMsgServiceInfoResponse, HorustMsgServiceReloadResponse, HorustMsgServiceStatus, HorustMsgServiceStatusResponse};
 use crate::UdsConnectionHandler;
 use anyhow::{anyhow, Result};
 use log::{error, info};
@@ -47,11 +47,13 @@ pub trait CommandsHandlerTrait {
                     info!("Requested status for {}", status_request.service_name);
 
                     let service_status = self.get_service_status(&status_request.service_name);
+                    let reason = self.get_service_status_reason(&status_request.service_name);
                     service_status
                         .map(|status| {
                             new_horust_msg_service_status_response(
                                 status_request.service_name,
-                                status
+                                status,
+                                reason,
                             )
                         })
                         .unwrap_or_else(|err| {
@@ -77,6 +79,35 @@ pub trait CommandsHandlerTrait {
                             ))
                         })
                 }
+                horust_msg_request::Request::ConfigRequest(config_request) => {
+                    info!("Requested config for {}", config_request.service_name);
+
+                    self.get_service_config(&config_request.service_name)
+                        .map(|config| {
+                            new_horust_msg_service_config_response(
+                                config_request.service_name,
+                                config,
+                            )
+                        })
+                        .unwrap_or_else(|err| {
+                            new_horust_msg_error_response(format!(
+                                "Error from config handler: {err}",
+                            ))
+                        })
+                }
+                horust_msg_request::Request::ReloadRequest(reload_request) => {
+                    info!("Requested reload for {}", reload_request.service_name);
+
+                    self.reload_service(&reload_request.service_name)
+                        .map(|()| {
+                            new_horust_msg_service_reload_response(reload_request.service_name)
+                        })
+                        .unwrap_or_else(|err| {
+                            new_horust_msg_error_response(format!(
+                                "Error from reload handler: {err}",
+                            ))
+                        })
+                }
                 horust_msg_request::Request::ChangeRequest(change_request) => {
                     info!(
                         "Requested service update for {} to {}",
@@ -105,13 +136,24 @@ pub trait CommandsHandlerTrait {
 
     fn get_service_status(&self, service_name: &str) -> Result<HorustMsgServiceStatus>;
 
+    /// Why the service is in its current status, if known.
+    fn get_service_status_reason(&self, _service_name: &str) -> Option<String> {
+        None
+    }
+
     fn get_service_info(&self, service_name: &str) -> Result<String>;
 
+    /// The configuration the service is running with, serialized as toml.
+    fn get_service_config(&self, service_name: &str) -> Result<String>;
+
     fn update_service_status(
         &self,
         service_name: &str,
         new_status: HorustChangeServiceStatus,
     ) -> Result<HorustMsgServiceStatus>;
+
+    /// Asks the running service to reload its configuration, without restarting it.
+    fn reload_service(&self, service_name: &str) -> Result<()>;
 }
 
 pub fn new_horust_msg_error_response(error: String) -> HorustMsgMessage {
@@ -129,6 +171,7 @@ pub fn new_horust_msg_error_response(error: String) -> HorustMsgMessage {
 pub fn new_horust_msg_service_status_response(
     service_name: String,
     status: HorustMsgServiceStatus,
+    reason: Option<String>,
 ) -> HorustMsgMessage {
     HorustMsgMessage {
         message_type: Some(horust_msg_message::MessageType::Response(
@@ -137,6 +180,7 @@ pub fn new_horust_msg_service_status_response(
                     HorustMsgServiceStatusResponse {
                         service_name,
                         service_status: status.into(),
+                        reason: reason.unwrap_or_default(),
                     },
                 )),
             },
@@ -179,3 +223,33 @@ pub fn new_horust_msg_service_info_response(
         )),
     }
 }
+
+pub fn new_horust_msg_service_config_response(
+    service_name: String,
+    config: String,
+) -> HorustMsgMessage {
+    HorustMsgMessage {
+        message_type: Some(horust_msg_message::MessageType::Response(
+            HorustMsgResponse {
+                response: Some(horust_msg_response::Response::ConfigResponse(
+                    HorustMsgServiceConfigResponse {
+                        service_name,
+                        config,
+                    },
+                )),
+            },
+        )),
+    }
+}
+
+pub fn new_horust_msg_service_reload_response(service_name: String) -> HorustMsgMessage {
+    HorustMsgMessage {
+        message_type: Some(horust_msg_message::MessageType::Response(
+            HorustMsgResponse {
+                response: Some(horust_msg_response::Response::ReloadResponse(
+                    HorustMsgServiceReloadResponse { service_name },
+                )),
+            },
+        )),
+    }
+}
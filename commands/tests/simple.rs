@@ -1,7 +1,9 @@
 use anyhow::Result;
 use std::os::unix::net::UnixListener;
 
-use horust_commands_lib::{ClientHandler, CommandsHandlerTrait, HorustMsgServiceStatus};
+use horust_commands_lib::{
+    ClientHandler, CommandsHandlerTrait, HorustChangeServiceStatus, HorustMsgServiceStatus,
+};
 use log::info;
 use std::path::PathBuf;
 use std::sync::{Arc, Barrier};
@@ -31,6 +33,10 @@ impl CommandsHandlerTrait for MockCommandsHandler {
         })
     }
 
+    fn get_service_status_reason(&self, service_name: &str) -> Option<String> {
+        (service_name == "Started").then(|| String::from("spawn failed"))
+    }
+
     fn get_service_info(&self, service_name: &str) -> Result<String> {
         Ok(match service_name {
             "Running" => String::from("ok"),
@@ -39,6 +45,10 @@ impl CommandsHandlerTrait for MockCommandsHandler {
         })
     }
 
+    fn get_service_config(&self, service_name: &str) -> Result<String> {
+        Ok(format!("name = \"{service_name}\""))
+    }
+
     fn update_service_status(
         &self,
         _service_name: &str,
@@ -50,6 +60,13 @@ impl CommandsHandlerTrait for MockCommandsHandler {
             _ => unimplemented!(),
         })
     }
+
+    fn reload_service(&self, service_name: &str) -> Result<()> {
+        match service_name {
+            "Running" => Ok(()),
+            _ => anyhow::bail!("Service {service_name} cannot be reloaded."),
+        }
+    }
 }
 fn init() {
     let _ = env_logger::builder().is_test(true).try_init();
@@ -72,6 +89,8 @@ fn test_simple() -> Result<()> {
         barrier_server.wait();
         uds.accept().unwrap();
         uds.accept().unwrap();
+        uds.accept().unwrap();
+        uds.accept().unwrap();
     });
 
     let c_handle = thread::spawn(move || {
@@ -79,8 +98,18 @@ fn test_simple() -> Result<()> {
         let client = ClientHandler::new_client(&socket_path).unwrap();
         client.client("Running".into()).unwrap();
 
-        let client = ClientHandler::new_client(&socket_path).unwrap();
-        client.client("Started".into()).unwrap();
+        let mut client = ClientHandler::new_client(&socket_path).unwrap();
+        let (_, status, reason) = client.send_status_request("Started".into()).unwrap();
+        assert_eq!(status, HorustMsgServiceStatus::Started);
+        assert_eq!(reason.as_deref(), Some("spawn failed"));
+
+        let mut client = ClientHandler::new_client(&socket_path).unwrap();
+        let service_name = client.send_reload_request("Running".into()).unwrap();
+        assert_eq!(service_name, "Running");
+
+        let mut client = ClientHandler::new_client(&socket_path).unwrap();
+        let err = client.send_reload_request("Started".into()).unwrap_err();
+        assert!(err.to_string().contains("cannot be reloaded"), "{}", err);
     });
     s_handle.join().unwrap();
     c_handle.join().unwrap();
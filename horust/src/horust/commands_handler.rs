@@ -1,5 +1,5 @@
 use crate::horust::bus::BusConnector;
-use crate::horust::formats::{ServiceName, ServiceStatus};
+use crate::horust::formats::{ExitReason, Service, ServiceName, ServiceStatus};
 use crate::horust::Event;
 use anyhow::{anyhow, bail, Result};
 use horust_commands_lib::{CommandsHandlerTrait, HorustMsgServiceStatus,HorustChangeServiceStatus};
@@ -15,7 +15,7 @@ use sysinfo::{ProcessRefreshKind, ProcessesToUpdate, System};
 pub fn spawn(
     bus: BusConnector<Event>,
     uds_path: PathBuf,
-    services: Vec<ServiceName>,
+    services: Vec<Service>,
 ) -> JoinHandle<()> {
     thread::spawn(move || {
         let mut commands_handler = CommandsHandler::new(bus, uds_path, services);
@@ -27,23 +27,31 @@ struct CommandsHandler {
     bus: BusConnector<Event>,
     services: HashMap<ServiceName, ServiceStatus>,
     services_pids: HashMap<ServiceName, Pid>,
+    services_configs: HashMap<ServiceName, Service>,
+    /// Why the last spawn of the service failed, until it's spawned successfully.
+    spawn_failures: HashMap<ServiceName, String>,
+    /// How the service has terminated the last time.
+    last_exits: HashMap<ServiceName, ExitReason>,
     uds_listener: UnixListener,
     uds_path: PathBuf,
 }
 
 impl CommandsHandler {
-    fn new(bus: BusConnector<Event>, uds_path: PathBuf, services: Vec<ServiceName>) -> Self {
+    fn new(bus: BusConnector<Event>, uds_path: PathBuf, services: Vec<Service>) -> Self {
         let uds_listener = UnixListener::bind(&uds_path).unwrap();
         uds_listener.set_nonblocking(true).unwrap();
         Self {
             bus,
             uds_path,
             uds_listener,
-            services_pids: services.clone().into_iter().map(|name| (name, Pid::from_raw(0))).collect(),
+            services_pids: services.iter().map(|s| (s.name.clone(), Pid::from_raw(0))).collect(),
             services: services
-                .into_iter()
-                .map(|s| (s, ServiceStatus::Initial))
+                .iter()
+                .map(|s| (s.name.clone(), ServiceStatus::Initial))
                 .collect(),
+            services_configs: services.into_iter().map(|s| (s.name.clone(), s)).collect(),
+            spawn_failures: HashMap::new(),
+            last_exits: HashMap::new(),
         }
     }
     fn run(&mut self) {
@@ -60,9 +68,16 @@ impl CommandsHandler {
                         return;
                     }
                     Event::PidChanged(name, pid) => {
+                        self.spawn_failures.remove(&name);
                         let k = self.services_pids.get_mut(&name).unwrap();
                         *k = pid;
                     }
+                    Event::SpawnFailed(name, reason) => {
+                        self.spawn_failures.insert(name, reason);
+                    }
+                    Event::ServiceExited(name, reason) => {
+                        self.last_exits.insert(name, reason);
+                    }
                     _ => {}
                 }
             }
@@ -83,6 +98,14 @@ impl CommandsHandlerTrait for CommandsHandler {
             .ok_or_else(|| anyhow!("Error: service {service_name} not found."))
     }
 
+    fn get_service_status_reason(&self, service_name: &str) -> Option<String> {
+        self.spawn_failures.get(service_name).cloned().or_else(|| {
+            self.last_exits
+                .get(service_name)
+                .map(|reason| format!("Last exit: {reason}."))
+        })
+    }
+
     fn get_service_info(&self, service_name: &str) -> Result<String> {
         if let Some(&pid) = self.services_pids.get(service_name)  {
             let mut sys = System::new_all();
@@ -108,6 +131,14 @@ impl CommandsHandlerTrait for CommandsHandler {
         }
     }
 
+    fn get_service_config(&self, service_name: &str) -> Result<String> {
+        let service = self
+            .services_configs
+            .get(service_name)
+            .ok_or_else(|| anyhow!("Error: service {service_name} not found."))?;
+        Ok(toml::to_string(service)?)
+    }
+
     fn update_service_status(
         &self,
         service_name: &str,
@@ -132,6 +163,22 @@ impl CommandsHandlerTrait for CommandsHandler {
         };
         self.get_service_status(service_name)
     }
+
+    fn reload_service(&self, service_name: &str) -> Result<()> {
+        let service = self
+            .services_configs
+            .get(service_name)
+            .ok_or_else(|| anyhow!("Service {service_name} not found."))?;
+        if !service.reload.is_defined() {
+            bail!("Service {service_name} has no reload signal or command.");
+        }
+        let status = &self.services[service_name];
+        if *status != ServiceStatus::Running {
+            bail!("Service {service_name} cannot be reloaded, it's in {status} status.");
+        }
+        self.bus.send_event(Event::Reload(service_name.to_string()));
+        Ok(())
+    }
 }
 
 fn from_service_status(status: &ServiceStatus) -> HorustMsgServiceStatus {
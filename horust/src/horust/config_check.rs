@@ -0,0 +1,319 @@
+//! Offline checks of the services configuration, used by `horust validate`.
+//! On top of what `validate` does before starting the services, it will also check that the
+//! system can actually run them: users, working directories, programs and log files.
+
+use std::ffi::OsString;
+use std::fmt::{Display, Formatter};
+use std::path::{Path, PathBuf};
+
+use nix::unistd::{access, AccessFlags};
+use serde::Serialize;
+
+use crate::horust::error::ValidationError;
+use crate::horust::formats::{
+    validate, Command, Group, HookKind, HorustConfig, LogOutput, Service, ServiceName,
+};
+use crate::horust::supervisor::{find_program, find_program_in};
+use crate::horust::{list_service_files, load_service};
+
+/// A problem found while checking the configuration.
+#[derive(Serialize, Clone, Debug, Eq, PartialEq)]
+pub struct ConfigProblem {
+    /// The file (or services path) where the problem was found.
+    pub file: Option<PathBuf>,
+    pub service: Option<ServiceName>,
+    /// The offending field, e.g. `termination.die-if-failed`.
+    pub field: Option<String>,
+    pub message: String,
+}
+
+impl Display for ConfigProblem {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match &self.file {
+            Some(file) => write!(f, "{}", file.display())?,
+            None => f.write_str("<unknown file>")?,
+        }
+        if let Some(field) = &self.field {
+            write!(f, " [{}]", field)?;
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+/// Loads the services from paths the same way Horust does before running them, and checks them
+/// together with the services defined in the `config` file at `config_path`.
+/// An empty result means that the configuration is valid.
+pub fn check_services(
+    config_path: &Path,
+    config: &HorustConfig,
+    paths: &[PathBuf],
+) -> Vec<ConfigProblem> {
+    let mut problems = vec![];
+    let mut services: Vec<(PathBuf, Service)> = config
+        .services
+        .iter()
+        .map(|service| (config_path.to_path_buf(), service.clone()))
+        .collect();
+    for path in paths {
+        match list_service_files(path) {
+            Ok(files) => {
+                for file in files {
+                    match load_service(&file, &config.defaults) {
+                        Ok(loaded) => {
+                            services.extend(loaded.into_iter().map(|s| (file.clone(), s)))
+                        }
+                        Err(err) => problems.push(ConfigProblem {
+                            file: Some(file),
+                            service: None,
+                            field: err
+                                .downcast_ref::<ValidationError>()
+                                .map(|error| error.field().to_string()),
+                            message: format!("{:#}", err),
+                        }),
+                    }
+                }
+            }
+            Err(err) => problems.push(ConfigProblem {
+                file: Some(path.clone()),
+                service: None,
+                field: None,
+                message: format!("Failed reading services path: {}", err),
+            }),
+        }
+    }
+
+    if let Err(errors) = validate(services.iter().map(|(_, s)| s.clone()).collect()) {
+        problems.extend(
+            errors
+                .errors()
+                .iter()
+                .flat_map(|error| validation_problems(error, &services)),
+        );
+    }
+    problems.extend(services.iter().flat_map(|(file, service)| {
+        check_environment(service)
+            .into_iter()
+            .map(|(field, message)| ConfigProblem {
+                file: Some(file.clone()),
+                service: Some(service.name.clone()),
+                field: Some(field.to_string()),
+                message,
+            })
+    }));
+    problems
+}
+
+/// Attaches the file(s) to the validation error. Duplicated names are reported once per file.
+fn validation_problems(
+    error: &ValidationError,
+    services: &[(PathBuf, Service)],
+) -> Vec<ConfigProblem> {
+    let mut files = services
+        .iter()
+        .filter(|(_, s)| s.name == error.service())
+        .map(|(file, _)| Some(file.clone()))
+        .collect::<Vec<_>>();
+    if !matches!(error, ValidationError::DuplicateName { .. }) {
+        files.truncate(1);
+    }
+    if files.is_empty() {
+        files.push(None);
+    }
+    files
+        .into_iter()
+        .map(|file| ConfigProblem {
+            file,
+            service: Some(error.service().to_string()),
+            field: Some(error.field().to_string()),
+            message: error.to_string(),
+        })
+        .collect()
+}
+
+/// Checks that the program of the command can be found.
+fn check_program(command: &Command, shell: bool, path_var: &Option<OsString>) -> Option<String> {
+    match command.args(shell) {
+        // `shell` with a list is already reported by `validate`.
+        Err(_) if shell => None,
+        Err(err) => Some(err.to_string()),
+        Ok(chunks) => match chunks.first() {
+            // Expanded on spawn, using the environment of the service.
+            Some(program) if program.contains('$') => None,
+            Some(program) if program.contains('/') && !Path::new(program).is_file() => {
+                Some(format!("Program {:?} not found.", program))
+            }
+            Some(program) if !program.contains('/') => {
+                let found = match path_var {
+                    Some(path_var) => find_program_in(program, path_var),
+                    None => find_program(program),
+                };
+                found.err().map(|err| err.to_string())
+            }
+            // Empty commands are already reported by `validate`.
+            _ => None,
+        },
+    }
+}
+
+/// Checks that the service can run on this system. Returns a list of (field, problem).
+fn check_environment(service: &Service) -> Vec<(&'static str, String)> {
+    let mut problems = vec![];
+    if let Err(err) = service.user.get_raw_user() {
+        problems.push(("user", format!("{:#}", err)));
+    }
+    if let Some(Err(err)) = service.group.as_ref().map(Group::get_gid) {
+        problems.push(("group", format!("{:#}", err)));
+    }
+    service
+        .supplementary_groups
+        .iter()
+        .flatten()
+        .filter_map(|group| group.get_gid().err())
+        .for_each(|err| problems.push(("supplementary-groups", format!("{:#}", err))));
+    if !service.working_directory.is_dir() {
+        problems.push((
+            "working-directory",
+            format!(
+                "Directory {} does not exist.",
+                service.working_directory.display()
+            ),
+        ));
+    }
+    // The program is looked up in the PATH the service will have. If the environment can't be
+    // created, the reason is reported below.
+    let path_var = service
+        .get_environment(0, Path::new(""))
+        .ok()
+        .and_then(|env| {
+            env.into_iter()
+                .find_map(|var| var.strip_prefix("PATH=").map(OsString::from))
+        });
+    if let Some(problem) = check_program(&service.command, service.shell, &path_var) {
+        problems.push(("command", problem));
+    }
+    HookKind::ALL
+        .into_iter()
+        .filter_map(|kind| service.hooks.get(kind))
+        .filter_map(|hook| check_program(&hook.command, false, &path_var))
+        .for_each(|problem| problems.push(("hooks", problem)));
+    let env_files = service
+        .environment
+        .env_files
+        .iter()
+        .chain(service.environment.from_file.values());
+    for path in env_files {
+        // Only check the access: the content might be a secret.
+        if let Err(errno) = access(path, AccessFlags::R_OK) {
+            problems.push((
+                "environment",
+                format!("{} is not readable: {}", path.display(), errno.desc()),
+            ));
+        }
+    }
+    let outputs = [
+        ("stdout", &service.stdout, service.stdout_rotate_size > 0),
+        ("stderr", &service.stderr, false),
+    ];
+    for (field, output, creates_parents) in outputs {
+        if let LogOutput::Path(path) = output {
+            if let Err(err) = check_writable(path, creates_parents) {
+                problems.push((field, err));
+            }
+        }
+    }
+    problems
+}
+
+/// Checks if Horust will be able to create or append to the file at path.
+/// If `creates_parents` is set, the missing parent directories will be created on spawn.
+fn check_writable(path: &Path, creates_parents: bool) -> Result<(), String> {
+    let target = if path.exists() {
+        path
+    } else {
+        let parent = match path.parent() {
+            Some(parent) if parent.as_os_str().is_empty() => Path::new("."),
+            Some(parent) => parent,
+            None => return Err(format!("Invalid log file path: {}", path.display())),
+        };
+        match parent.ancestors().find(|p| p.exists()) {
+            Some(existing) if creates_parents || existing == parent => existing,
+            _ => {
+                return Err(format!(
+                    "Cannot create {}: directory {} does not exist.",
+                    path.display(),
+                    parent.display()
+                ))
+            }
+        }
+    };
+    access(target, AccessFlags::W_OK)
+        .map_err(|errno| format!("{} is not writable: {}", target.display(), errno.desc()))
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use tempfile::TempDir;
+
+    use crate::horust::config_check::check_services;
+    use crate::horust::formats::{Command, HorustConfig, Service};
+
+    #[test]
+    fn test_check_services() {
+        let tempdir = TempDir::with_prefix("horust-check").unwrap();
+        let path = tempdir.path();
+        fs::write(path.join("a.toml"), r#"command = "/bin/sh -c 'exit 0'""#).unwrap();
+        let config_path = path.join("horust.toml");
+        let config = HorustConfig::default();
+        let problems = check_services(&config_path, &config, &[path.to_path_buf()]);
+        assert!(problems.is_empty(), "{:?}", problems);
+
+        fs::write(path.join("b.toml"), "not a toml file").unwrap();
+        fs::write(
+            path.join("c.toml"),
+            r#"command = "surely-not-on-path-horust"
+start-after = ["missing.toml"]
+stderr = "/surely/not/existing/horust/stderr.log"
+group = "surely-not-a-group-horust"
+[environment]
+env-files = ["/surely/not/existing/horust/app.env"]
+[hooks]
+pre-start = { command = "surely-not-on-path-horust" }
+"#,
+        )
+        .unwrap();
+        let inline = Service {
+            name: "a.toml".into(),
+            command: Command::Line("/bin/true".into()),
+            ..Default::default()
+        };
+        let config = HorustConfig {
+            services: vec![inline],
+            ..Default::default()
+        };
+        let problems = check_services(&config_path, &config, &[path.to_path_buf()]);
+        let mut fields: Vec<(String, Option<String>)> = problems
+            .iter()
+            .map(|p| {
+                let file = p.file.as_ref().unwrap().file_name().unwrap();
+                (file.to_string_lossy().to_string(), p.field.clone())
+            })
+            .collect();
+        fields.sort();
+        assert_eq!(
+            fields,
+            vec![
+                ("a.toml".to_string(), Some("name".to_string())),
+                ("b.toml".to_string(), None),
+                ("c.toml".to_string(), Some("command".to_string())),
+                ("c.toml".to_string(), Some("environment".to_string())),
+                ("c.toml".to_string(), Some("group".to_string())),
+                ("c.toml".to_string(), Some("hooks".to_string())),
+                ("c.toml".to_string(), Some("start-after".to_string())),
+                ("c.toml".to_string(), Some("stderr".to_string())),
+                ("horust.toml".to_string(), Some("name".to_string())),
+            ]
+        );
+    }
+}
@@ -1,3 +1,6 @@
+use crate::horust::formats::{HookFailure, HookKind};
+use std::path::PathBuf;
+
 #[derive(Debug, thiserror::Error)]
 #[error("Found following errors during validation phase: {}", validation_errors(.0))]
 pub struct ValidationErrors(Vec<ValidationError>);
@@ -6,6 +9,10 @@ impl ValidationErrors {
     pub fn new(errors: Vec<ValidationError>) -> Self {
         Self(errors)
     }
+
+    pub fn errors(&self) -> &[ValidationError] {
+        &self.0
+    }
 }
 
 fn validation_errors(errors: &[ValidationError]) -> String {
@@ -24,4 +31,104 @@ pub enum ValidationError {
     MissingDependency { before: String, after: String },
     #[error("Command is defined, but it is empty for service: {service}")]
     CommandEmpty { service: String },
+    #[error(
+        "Service '{service}' has the negative successful exit code {code}. Killed services have no \
+        exit code: use `successful-signals = [\"KILL\"]` instead of -137."
+    )]
+    NegativeSuccessfulExitCode { service: String, code: i32 },
+    #[error("Service '{service}' has `shell` set, but its command is a list of arguments.")]
+    ShellWithArgs { service: String },
+    #[error("The {hook} hook of service '{service}' has an empty command.")]
+    HookCommandEmpty { service: String, hook: HookKind },
+    #[error("The {hook} hook of service '{service}' cannot use `on-failure = \"{policy}\"`.")]
+    HookFailurePolicy {
+        service: String,
+        hook: HookKind,
+        policy: HookFailure,
+    },
+    #[error("Service '{service}' has both a reload signal and a reload command.")]
+    ReloadSignalAndCommand { service: String },
+    #[error("The reload command of service '{service}' is empty.")]
+    ReloadCommandEmpty { service: String },
+    #[error("The soft `{resource}` rlimit of service '{service}' is above the hard one.")]
+    RlimitSoftAboveHard {
+        service: String,
+        resource: &'static str,
+    },
+    #[error("The `{attribute}` of service '{service}' is {value}, not in {min}..={max}.")]
+    ProcessAttributeOutOfRange {
+        service: String,
+        attribute: &'static str,
+        value: i64,
+        min: i64,
+        max: i64,
+    },
+    #[error("Service '{service}' sets a sandbox hostname without the `uts` namespace.")]
+    SandboxHostnameWithoutUts { service: String },
+    #[error("The read-only path {} of service '{service}' is not absolute.", .path.display())]
+    SandboxRelativePath { service: String, path: PathBuf },
+    #[error("Service '{service}' cannot start after itself.")]
+    SelfDependency { service: String },
+    #[error("Found a start-after dependency cycle: {}", .cycle.join(" -> "))]
+    DependencyCycle { cycle: Vec<String> },
+    #[error("Service name '{service}' is defined more than once.")]
+    DuplicateName { service: String },
+    #[error(
+        "Service '{service}' should die if '{target}' fails, but there is no service with such name."
+    )]
+    MissingDieIfFailed { service: String, target: String },
+    #[error("Template service '{service}' has no instances.")]
+    NoInstances { service: String },
+    #[error("Found an `extends` inheritance cycle: {}", .chain.join(" -> "))]
+    InheritanceCycle { chain: Vec<String> },
+}
+
+impl ValidationError {
+    /// The name of the service this error was found in.
+    pub fn service(&self) -> &str {
+        match self {
+            ValidationError::MissingDependency { after, .. } => after,
+            ValidationError::CommandEmpty { service }
+            | ValidationError::NegativeSuccessfulExitCode { service, .. }
+            | ValidationError::ShellWithArgs { service }
+            | ValidationError::HookCommandEmpty { service, .. }
+            | ValidationError::HookFailurePolicy { service, .. }
+            | ValidationError::ReloadSignalAndCommand { service }
+            | ValidationError::ReloadCommandEmpty { service }
+            | ValidationError::RlimitSoftAboveHard { service, .. }
+            | ValidationError::ProcessAttributeOutOfRange { service, .. }
+            | ValidationError::SandboxHostnameWithoutUts { service }
+            | ValidationError::SandboxRelativePath { service, .. }
+            | ValidationError::SelfDependency { service }
+            | ValidationError::DuplicateName { service }
+            | ValidationError::MissingDieIfFailed { service, .. }
+            | ValidationError::NoInstances { service } => service,
+            ValidationError::DependencyCycle { cycle } => cycle.first().unwrap(),
+            ValidationError::InheritanceCycle { chain } => chain.first().unwrap(),
+        }
+    }
+
+    /// The configuration field which caused this error.
+    pub fn field(&self) -> &'static str {
+        match self {
+            ValidationError::MissingDependency { .. }
+            | ValidationError::SelfDependency { .. }
+            | ValidationError::DependencyCycle { .. } => "start-after",
+            ValidationError::CommandEmpty { .. } => "command",
+            ValidationError::NegativeSuccessfulExitCode { .. } => "failure.successful-exit-code",
+            ValidationError::ShellWithArgs { .. } => "shell",
+            ValidationError::HookCommandEmpty { .. }
+            | ValidationError::HookFailurePolicy { .. } => "hooks",
+            ValidationError::ReloadSignalAndCommand { .. }
+            | ValidationError::ReloadCommandEmpty { .. } => "reload",
+            ValidationError::RlimitSoftAboveHard { .. } => "rlimits",
+            ValidationError::ProcessAttributeOutOfRange { .. } => "process",
+            ValidationError::SandboxHostnameWithoutUts { .. }
+            | ValidationError::SandboxRelativePath { .. } => "sandbox",
+            ValidationError::DuplicateName { .. } => "name",
+            ValidationError::MissingDieIfFailed { .. } => "termination.die-if-failed",
+            ValidationError::NoInstances { .. } => "instances",
+            ValidationError::InheritanceCycle { .. } => "extends",
+        }
+    }
 }
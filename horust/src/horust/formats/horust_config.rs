@@ -1,43 +1,144 @@
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
-use anyhow::Result;
+use anyhow::{bail, Result};
 use serde::{Deserialize, Serialize};
 
-// TODO: this should be an optional
-// otherwise we wouldn't know if it was set to false on the commandline. Maybe. Because it's a flag.
+use crate::horust::formats::{expand_config, expand_value, merge_defaults, Service};
 
+/// Horust's global configuration. Every option can be set, from the highest precedence:
+/// via cmdline, via `HORUST_<OPTION>` env vars, via config file, or left to its default.
+/// Optional values are `None` when not set, so that they can be explicitly set to false.
 #[derive(Debug, clap::Parser, Serialize, Deserialize, Default)]
 pub struct HorustConfig {
-    #[clap(long)]
+    #[clap(
+        long,
+        env = "HORUST_UNSUCCESSFUL_EXIT_FINISHED_FAILED",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "true",
+        value_parser = clap::builder::BoolishValueParser::new()
+    )]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     /// Exits with an unsuccessful exit code if any process is in FinishedFailed state
-    pub unsuccessful_exit_finished_failed: bool,
+    pub unsuccessful_exit_finished_failed: Option<bool>,
+
+    #[clap(skip)]
+    #[serde(
+        default,
+        rename = "services-paths",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    /// Paths to service files or directories, loaded together with the `--services-path` ones.
+    pub services_paths: Vec<PathBuf>,
+
+    #[clap(skip)]
+    #[serde(default, rename = "service", skip_serializing_if = "Vec::is_empty")]
+    /// Services defined inline in the config file, as `[[service]]` tables.
+    pub services: Vec<Service>,
+
+    #[clap(skip)]
+    #[serde(default, skip_serializing_if = "toml::Table::is_empty")]
+    /// Fallback values for the fields of every service, e.g. `[defaults.termination]`.
+    /// Values defined by the services have precedence.
+    pub defaults: toml::Table,
 }
 
 impl HorustConfig {
     /// Load the config file, and handles the merge with the options defined in the cmdline.
-    /// Cmdline defined values have precedence over config based values.
+    /// Cmdline (and env) defined values have precedence over config based values.
+    /// The config file is templated from env like the service files.
     pub fn load_and_merge(cmd_line: &HorustConfig, path: &Path) -> Result<Self> {
-        let config_file: HorustConfig = if path.exists() {
+        let mut config: toml::Table = if path.exists() {
             let content = std::fs::read_to_string(path)?;
-            toml::from_str(&content)?
+            let mut config = toml::from_str(&content)?;
+            expand_config_file(&mut config)?;
+            config
         } else {
-            Default::default()
+            toml::Table::new()
         };
+        // Only the options which were set are serialized, so they can just override the file.
+        config.extend(toml::Table::try_from(cmd_line)?);
+        apply_defaults_to_inline_services(&mut config)?;
+        let mut config: HorustConfig = toml::Value::Table(config).try_into()?;
 
-        let unsuccessful_exit_finished_failed = cmd_line.unsuccessful_exit_finished_failed
-            || config_file.unsuccessful_exit_finished_failed;
+        for service in &config.services {
+            if service.name.is_empty() {
+                bail!("Services defined in the config file must have a `name`.");
+            }
+            if service.instances.is_some() {
+                bail!(
+                    "Service '{}': template services can't be defined in the config file.",
+                    service.name
+                );
+            }
+        }
 
-        Ok(HorustConfig {
-            unsuccessful_exit_finished_failed,
-        })
+        // Relative services paths are relative to the config file.
+        let config_dir = path.parent().unwrap_or(Path::new(""));
+        config.services_paths = config
+            .services_paths
+            .into_iter()
+            .map(|services_path| config_dir.join(services_path))
+            .collect();
+        Ok(config)
+    }
+
+    pub fn unsuccessful_exit_finished_failed(&self) -> bool {
+        self.unsuccessful_exit_finished_failed.unwrap_or(false)
     }
 }
 
+/// Templates the raw config like the service files. The `[defaults]` and the `[[service]]` tables
+/// are templated as services, so their commands are expanded only when they are spawned.
+fn expand_config_file(config: &mut toml::Table) -> Result<()> {
+    for (key, value) in config.iter_mut() {
+        match (key.as_str(), value) {
+            ("defaults", toml::Value::Table(defaults)) => {
+                expand_config(defaults, None)?;
+            }
+            ("service", toml::Value::Array(services)) => {
+                for service in services.iter_mut().filter_map(toml::Value::as_table_mut) {
+                    expand_config(service, None)?;
+                }
+            }
+            (_, value) => {
+                expand_value(value, &mut vec![key.clone()], None)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Fills the `[[service]]` tables of the raw config with the `[defaults]`.
+fn apply_defaults_to_inline_services(config: &mut toml::Table) -> Result<()> {
+    let defaults = match config.get("defaults") {
+        Some(toml::Value::Table(defaults)) => defaults.clone(),
+        Some(_) => bail!("`defaults` should be a table."),
+        None => return Ok(()),
+    };
+    if defaults.contains_key("name") {
+        bail!("Services' `name` can't have a default value.");
+    }
+    if let Some(toml::Value::Array(services)) = config.get_mut("service") {
+        for service in services {
+            if let toml::Value::Table(service) = service {
+                merge_defaults(service, &defaults);
+            }
+        }
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod test {
+    use std::path::PathBuf;
+    use std::time::Duration;
+
     use anyhow::Result;
+    use clap::Parser;
     use tempfile::TempDir;
 
+    use crate::horust::formats::{TerminationSignal, User};
     use crate::horust::HorustConfig;
     #[test]
     fn test_load_and_merge() -> Result<()> {
@@ -45,9 +146,140 @@ mod test {
         let config_path = tempdir.path().join("config.toml");
         std::fs::write(&config_path, "Not a toml file :( ")?;
         let config = HorustConfig {
-            unsuccessful_exit_finished_failed: true,
+            unsuccessful_exit_finished_failed: Some(true),
+            ..Default::default()
         };
         HorustConfig::load_and_merge(&config, &config_path).unwrap_err();
+
+        std::fs::write(&config_path, "unsuccessful_exit_finished_failed = true")?;
+        let loaded = HorustConfig::load_and_merge(&Default::default(), &config_path)?;
+        assert!(loaded.unsuccessful_exit_finished_failed());
+        // Explicitly false from cmdline wins over the config file.
+        let config = HorustConfig {
+            unsuccessful_exit_finished_failed: Some(false),
+            ..Default::default()
+        };
+        let loaded = HorustConfig::load_and_merge(&config, &config_path)?;
+        assert!(!loaded.unsuccessful_exit_finished_failed());
+        let loaded =
+            HorustConfig::load_and_merge(&Default::default(), &tempdir.path().join("nope"))?;
+        assert!(!loaded.unsuccessful_exit_finished_failed());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_cmdline_and_env() {
+        let parse = |args: &[&str]| {
+            HorustConfig::try_parse_from(std::iter::once("horust").chain(args.iter().copied()))
+                .unwrap()
+                .unsuccessful_exit_finished_failed
+        };
+        assert_eq!(parse(&[]), None);
+        assert_eq!(parse(&["--unsuccessful-exit-finished-failed"]), Some(true));
+        assert_eq!(
+            parse(&["--unsuccessful-exit-finished-failed=false"]),
+            Some(false)
+        );
+        std::env::set_var("HORUST_UNSUCCESSFUL_EXIT_FINISHED_FAILED", "yes");
+        assert_eq!(parse(&[]), Some(true));
+        assert_eq!(
+            parse(&["--unsuccessful-exit-finished-failed=no"]),
+            Some(false)
+        );
+        std::env::remove_var("HORUST_UNSUCCESSFUL_EXIT_FINISHED_FAILED");
+    }
+
+    #[test]
+    fn test_load_inline_services() -> Result<()> {
+        let tempdir = TempDir::with_prefix("load-inline-services")?;
+        let config_path = tempdir.path().join("config.toml");
+        let content = r#"services-paths = ["/etc/horust/services", "./extra"]
+
+[[service]]
+name = "web"
+command = "/usr/bin/web"
+start-after = ["database"]
+
+[[service]]
+name = "database"
+command = "/usr/bin/database"
+[service.termination]
+wait = "20s"
+"#;
+        std::fs::write(&config_path, content)?;
+        let config = HorustConfig::load_and_merge(&Default::default(), &config_path)?;
+        assert_eq!(
+            config.services_paths,
+            vec![
+                PathBuf::from("/etc/horust/services"),
+                tempdir.path().join("./extra")
+            ]
+        );
+        let names: Vec<&str> = config.services.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, vec!["web", "database"]);
+        assert_eq!(config.services[0].start_after, vec!["database"]);
+        assert_eq!(config.services[1].termination.wait, Duration::from_secs(20));
+
+        std::fs::write(&config_path, "[[service]]\ncommand = \"/usr/bin/web\"")?;
+        HorustConfig::load_and_merge(&Default::default(), &config_path).unwrap_err();
+        Ok(())
+    }
+
+    #[test]
+    fn test_load_defaults() -> Result<()> {
+        let tempdir = TempDir::with_prefix("load-defaults")?;
+        let config_path = tempdir.path().join("config.toml");
+        let content = r#"[defaults]
+user = "nobody"
+[defaults.termination]
+wait = "30s"
+signal = "INT"
+[defaults.failure]
+successful-exit-code = [0, 1]
+
+[[service]]
+name = "web"
+command = "/usr/bin/web"
+[service.termination]
+wait = "5s"
+[service.failure]
+successful-exit-code = [2]
+"#;
+        std::fs::write(&config_path, content)?;
+        let config = HorustConfig::load_and_merge(&Default::default(), &config_path)?;
+        let service = &config.services[0];
+        assert_eq!(service.user, User::Name("nobody".into()));
+        assert_eq!(service.termination.wait, Duration::from_secs(5));
+        assert_eq!(service.termination.signal, TerminationSignal::INT);
+        assert_eq!(service.failure.successful_exit_code, vec![2]);
+        assert_eq!(config.defaults["user"].as_str(), Some("nobody"));
+
+        std::fs::write(&config_path, "[defaults]\nname = \"web\"")?;
+        HorustConfig::load_and_merge(&Default::default(), &config_path).unwrap_err();
+        Ok(())
+    }
+
+    #[test]
+    fn test_load_templated() -> Result<()> {
+        let tempdir = TempDir::with_prefix("load-templated")?;
+        let config_path = tempdir.path().join("config.toml");
+        std::env::set_var("HORUST_TEST_CONFIG_DIR", "/var/log/web");
+        let content = r#"[defaults]
+stdout = "${HORUST_TEST_CONFIG_DIR}/stdout"
+
+[[service]]
+name = "web"
+command = "/usr/bin/web --port $PORT"
+stderr = "${HORUST_TEST_CONFIG_DIR}/stderr"
+"#;
+        std::fs::write(&config_path, content)?;
+        let config = HorustConfig::load_and_merge(&Default::default(), &config_path)?;
+        let service = &config.services[0];
+        // Commands are expanded only when the service is spawned.
+        assert_eq!(service.command.to_string(), "/usr/bin/web --port $PORT");
+        assert_eq!(service.stdout, "/var/log/web/stdout".into());
+        assert_eq!(service.stderr, "/var/log/web/stderr".into());
+        std::env::remove_var("HORUST_TEST_CONFIG_DIR");
         Ok(())
     }
 }
@@ -1,3 +1,6 @@
+use std::fmt::{Display, Formatter};
+
+use nix::sys::signal::Signal;
 use nix::unistd::Pid;
 
 pub use horust_config::HorustConfig;
@@ -19,10 +22,19 @@ pub enum Event {
     StatusUpdate(ServiceName, ServiceStatus),
     // This event represents a status change.
     StatusChanged(ServiceName, ServiceStatus),
-    ServiceExited(ServiceName, i32),
+    ServiceExited(ServiceName, ExitReason),
     ForceKill(ServiceName),
     Kill(ServiceName),
-    SpawnFailed(ServiceName),
+    /// Send the signal of this step of the termination sequence.
+    TerminationStep(ServiceName, usize),
+    /// The service couldn't be spawned, with the reason.
+    SpawnFailed(ServiceName, String),
+    /// A lifecycle hook of the service has finished, with the reason if it has failed.
+    HookFinished(ServiceName, HookKind, Result<(), String>),
+    /// Ask the running service to reload its configuration.
+    Reload(ServiceName),
+    /// The reload of the service has finished, with the reason if it has failed.
+    ReloadFinished(ServiceName, Result<(), String>),
     Run(ServiceName),
     ShuttingDownInitiated(ShuttingDown),
     HealthCheck(ServiceName, HealthinessStatus),
@@ -38,12 +50,38 @@ impl Event {
     pub fn new_status_update(service_name: &str, status: ServiceStatus) -> Self {
         Self::StatusUpdate(service_name.to_string(), status)
     }
-    pub(crate) fn new_service_exited(service_name: ServiceName, exit_status: i32) -> Self {
-        Self::ServiceExited(service_name, exit_status)
+    pub(crate) fn new_service_exited(service_name: ServiceName, reason: ExitReason) -> Self {
+        Self::ServiceExited(service_name, reason)
     }
     pub(crate) fn new_force_kill(service_name: &str) -> Self {
         Self::ForceKill(service_name.to_string())
     }
+    pub(crate) fn new_termination_step(service_name: &str, step: usize) -> Self {
+        Self::TerminationStep(service_name.to_string(), step)
+    }
+}
+
+/// How the process of a service has terminated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExitReason {
+    /// Exited with an exit code.
+    Exited(i32),
+    /// Killed by a signal.
+    Signaled(Signal),
+    /// Killed by a signal, and it dumped core.
+    CoreDumped(Signal),
+}
+
+impl Display for ExitReason {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExitReason::Exited(code) => write!(f, "exited with code {}", code),
+            ExitReason::Signaled(signal) => write!(f, "killed by signal {}", signal),
+            ExitReason::CoreDumped(signal) => {
+                write!(f, "killed by signal {} (core dumped)", signal)
+            }
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
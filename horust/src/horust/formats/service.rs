@@ -1,13 +1,16 @@
 use anyhow::{Context, Error, Result};
 use libcgroups::common::{
-    create_cgroup_manager, CgroupConfig, CgroupManager, ControllerOpt, DEFAULT_CGROUP_ROOT,
+    create_cgroup_manager, AnyCgroupManager, CgroupConfig, CgroupManager, ControllerOpt,
+    DEFAULT_CGROUP_ROOT,
 };
+use nix::sched::CloneFlags;
+use nix::sys::resource::{rlim_t, Resource, RLIM_INFINITY};
 use nix::sys::signal::Signal;
 use nix::unistd;
 use oci_spec::runtime::{LinuxCpuBuilder, LinuxMemoryBuilder, LinuxPidsBuilder, LinuxResources};
-use serde::de::{self, Visitor};
+use serde::de::{self, DeserializeOwned, Visitor};
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::ffi::OsStr;
 use std::fmt::{Debug, Formatter};
 use std::path::{Path, PathBuf};
@@ -16,6 +19,7 @@ use std::time::Duration;
 use std::{env, os::fd::RawFd};
 
 use crate::horust::error::{ValidationError, ValidationErrors};
+use crate::horust::formats::ExitReason;
 
 pub fn get_sample_service() -> &'static str {
     include_str!("../../../example_services/sample_service.toml")
@@ -23,21 +27,42 @@ pub fn get_sample_service() -> &'static str {
 
 pub type ServiceName = String;
 
+/// Environment variable replaced with the instance id in template services.
+pub const INSTANCE_VAR: &str = "INSTANCE";
+/// Key of the base service file a service inherits from, relative to the service's directory.
+const EXTENDS_KEY: &str = "extends";
+/// Key marking a service file as a base for other services, which won't be started on its own.
+const ABSTRACT_KEY: &str = "abstract";
+
 #[derive(Serialize, Clone, Deserialize, Debug, Eq, PartialEq)]
 #[serde(rename_all = "kebab-case", deny_unknown_fields)]
 pub struct Service {
     #[serde(default)]
     pub name: ServiceName,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    /// If set, this service is a template and one service per instance will be created.
+    pub instances: Option<Instances>,
+    #[serde(skip)]
+    /// The instance id, if this service was created from a template.
+    pub instance: Option<String>,
     #[serde()]
-    pub command: String,
+    pub command: Command,
+    /// Run the command through `/bin/sh -c`.
+    #[serde(default)]
+    pub shell: bool,
     #[serde(default)]
     pub user: User,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    /// Run with this group instead of the primary group of the user.
+    pub group: Option<Group>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    /// If unset, the groups the user is a member of are used.
+    pub supplementary_groups: Option<Vec<Group>>,
     #[serde(default = "Service::default_working_directory")]
     pub working_directory: PathBuf,
     #[serde(default = "Service::default_stdout_log")]
     pub stdout: LogOutput,
-    // todo: provide serialize_with
-    #[serde(default, skip_serializing, deserialize_with = "str_to_bytes")]
+    #[serde(default, deserialize_with = "str_to_bytes")]
     pub stdout_rotate_size: u64,
     #[serde(default = "default_as_false")]
     pub stdout_should_append_timestamp_to_filename: bool,
@@ -60,7 +85,63 @@ pub struct Service {
     #[serde(default)]
     pub termination: Termination,
     #[serde(default)]
+    pub hooks: Hooks,
+    #[serde(default)]
+    pub reload: Reload,
+    #[serde(default)]
     pub resource_limit: ResourceLimit,
+    #[serde(default)]
+    pub rlimits: Rlimits,
+    #[serde(default)]
+    pub process: ProcessAttributes,
+    #[serde(default)]
+    pub sandbox: Sandbox,
+}
+
+/// The command of a service: either a command line, or the list of its arguments.
+#[derive(Serialize, Clone, Deserialize, Debug, Eq, PartialEq)]
+#[serde(untagged)]
+pub enum Command {
+    /// Split into arguments like a shell would do, or run with `/bin/sh -c` if `shell` is set.
+    Line(String),
+    /// Passed as it is to `exec`.
+    Args(Vec<String>),
+}
+
+impl Command {
+    pub fn is_empty(&self) -> bool {
+        match self {
+            Command::Line(line) => line.trim().is_empty(),
+            Command::Args(args) => args.first().map_or(true, |program| program.is_empty()),
+        }
+    }
+
+    /// Returns the arguments for `exec`, the first one being the program.
+    pub fn args(&self, shell: bool) -> Result<Vec<String>> {
+        match (self, shell) {
+            (Command::Line(line), false) => {
+                shlex::split(line).with_context(|| format!("Invalid command: {}", line))
+            }
+            (Command::Line(line), true) => Ok(vec!["/bin/sh".into(), "-c".into(), line.clone()]),
+            (Command::Args(args), false) => Ok(args.clone()),
+            (Command::Args(_), true) => {
+                anyhow::bail!("`shell` can only be used with a command line, not with a list.")
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for Command {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Command::Line(line) => write!(f, "{}", line),
+            Command::Args(args) => {
+                let joined = shlex::try_join(args.iter().map(String::as_str))
+                    .unwrap_or_else(|_| args.join(" "));
+                write!(f, "{}", joined)
+            }
+        }
+    }
 }
 
 fn default_as_false() -> bool {
@@ -84,28 +165,146 @@ impl Service {
     /// Config will be automatically templated from env.
     /// Correct syntax is required for templating to work.
     /// Currently only templating from environment is implemented.
+    /// The format is detected from the file extension, check `ConfigFormat`.
     pub fn from_file<P>(path: &P) -> Result<Self>
     where
         P: AsRef<Path> + ?Sized + AsRef<OsStr> + Debug,
     {
-        let preconfig = std::fs::read_to_string(path)?;
-        let postconfig = shellexpand::full(&preconfig)?;
-        Ok(toml::from_str::<Service>(&postconfig)?)
+        let path: &Path = path.as_ref();
+        Self::from_files(&[path.to_path_buf()], None, &toml::Table::new())
     }
+
+    /// Loads the service out of files: the first one is the service definition, and the others
+    /// are drop-in fragments deep-merged on top of it, in order. Check `merge_config`.
+    /// If the service `extends` a base service file, the base is merged under the definition.
+    /// Missing values are taken from `defaults`, check `merge_defaults`.
+    pub(crate) fn from_files(
+        files: &[PathBuf],
+        instance: Option<&str>,
+        defaults: &toml::Table,
+    ) -> Result<Self> {
+        let (file, dropins) = files.split_first().context("Missing service file.")?;
+        let format = ConfigFormat::from_path(file);
+        let preconfig = std::fs::read_to_string(file)?;
+        let mut config: toml::Table = format.parse(&preconfig)?;
+        let templated = expand_config(&mut config, instance)?;
+        if !templated
+            && dropins.is_empty()
+            && defaults.is_empty()
+            && !config.contains_key(EXTENDS_KEY)
+            && !config.contains_key(ABSTRACT_KEY)
+        {
+            // Deserializing from the source keeps the line numbers in the error messages.
+            return format.parse(&preconfig);
+        }
+        config.remove(ABSTRACT_KEY);
+        if let Some(extends) = config.remove(EXTENDS_KEY) {
+            let mut chain = vec![file.canonicalize()?];
+            let mut base = load_base_config(file, extends, instance, &mut chain)?;
+            merge_extended_config(&mut base, config);
+            config = base;
+        }
+        for dropin in dropins {
+            merge_config(&mut config, read_config(dropin, instance)?);
+        }
+        merge_defaults(&mut config, defaults);
+        debug!(
+            "Effective configuration of {:?}:\n{}",
+            file,
+            toml::to_string(&config)?
+        );
+        Ok(toml::Value::Table(config).try_into::<Service>()?)
+    }
+
+    /// Returns true if the service file is `abstract`: it's only meant to be extended by other
+    /// services, and it won't be started.
+    pub(crate) fn is_abstract_file(path: &Path) -> Result<bool> {
+        match read_config(path, None)?.get(ABSTRACT_KEY) {
+            None => Ok(false),
+            Some(toml::Value::Boolean(is_abstract)) => Ok(*is_abstract),
+            Some(value) => {
+                anyhow::bail!("`{}` should be a boolean, found: {}", ABSTRACT_KEY, value)
+            }
+        }
+    }
+
+    /// Like `from_str`, but `${INSTANCE}` will be templated with the instance id.
+    pub(crate) fn from_str_with_instance(s: &str, instance: Option<&str>) -> Result<Self> {
+        let mut config: toml::Table = toml::from_str(s)?;
+        if !expand_config(&mut config, instance)? {
+            // Deserializing from the source keeps the line numbers in the error messages.
+            return Ok(toml::from_str::<Service>(s)?);
+        }
+        Ok(toml::Value::Table(config).try_into::<Service>()?)
+    }
+
+    /// Creates one service per instance of this template service, named `<base_name>@<id>`.
+    /// `files` are the template's source, they will be templated again for every instance.
+    pub(crate) fn instantiate(
+        &self,
+        files: &[PathBuf],
+        base_name: &str,
+        defaults: &toml::Table,
+    ) -> Result<Vec<Self>> {
+        let ids = self
+            .instances
+            .as_ref()
+            .map(Instances::ids)
+            .unwrap_or_default();
+        if ids.is_empty() {
+            return Err(ValidationError::NoInstances {
+                service: base_name.to_string(),
+            }
+            .into());
+        }
+        ids.into_iter()
+            .map(|id| {
+                let mut service = Self::from_files(files, Some(&id), defaults)
+                    .with_context(|| format!("Failed creating instance: {}", id))?;
+                service.name = format!("{}@{}", base_name, id);
+                service.instances = None;
+                service.instance = Some(id);
+                Ok(service)
+            })
+            .collect()
+    }
+
+    /// The instance id, if this service was created from a template.
+    pub fn instance(&self) -> Option<&str> {
+        self.instance.as_deref()
+    }
+
     /// Creates the environment K=V variables, used for exec into the new process.
     /// User defined environment variables overwrite the predefined values.
-    pub fn get_environment(&self) -> Result<Vec<String>> {
-        Ok(self.environment.get_environment(
+    /// `restart_count` and `socket_path` are exported, together with other supervisor's metadata.
+    pub fn get_environment(&self, restart_count: u32, socket_path: &Path) -> Result<Vec<String>> {
+        let mut metadata = vec![
+            ("HORUST_SERVICE_NAME".to_string(), self.name.clone()),
+            (
+                "HORUST_RESTART_COUNT".to_string(),
+                restart_count.to_string(),
+            ),
+            ("HORUST_PID".to_string(), unistd::getpid().to_string()),
+            (
+                "HORUST_SOCKET_PATH".to_string(),
+                socket_path.display().to_string(),
+            ),
+        ];
+        if let Some(instance) = self.instance() {
+            metadata.push(("HORUST_INSTANCE".to_string(), instance.to_string()));
+        }
+        self.environment.get_environment(
             self.user.clone().get_name()?,
             self.user.clone().get_home()?.display().to_string(),
-        ))
+            metadata,
+        )
     }
 
     /// Wrapper for single command horust run
     pub fn from_command(command: String) -> Self {
         Service {
             name: command.clone(),
-            command,
+            command: Command::Line(command),
             ..Default::default()
         }
     }
@@ -115,6 +314,8 @@ impl Default for Service {
     fn default() -> Self {
         Self {
             name: "".to_owned(),
+            instances: None,
+            instance: None,
             start_after: Default::default(),
             working_directory: env::current_dir().unwrap(),
             stdout: Default::default(),
@@ -122,15 +323,23 @@ impl Default for Service {
             stdout_should_append_timestamp_to_filename: Default::default(),
             stderr: Default::default(),
             user: Default::default(),
+            group: None,
+            supplementary_groups: None,
             restart: Default::default(),
             start_delay: Duration::from_secs(0),
-            command: "command".to_string(),
+            command: Command::Line("command".to_string()),
+            shell: false,
             healthiness: Default::default(),
             signal_rewrite: None,
             environment: Default::default(),
             failure: Default::default(),
             termination: Default::default(),
+            hooks: Default::default(),
+            reload: Default::default(),
             resource_limit: Default::default(),
+            rlimits: Default::default(),
+            process: Default::default(),
+            sandbox: Default::default(),
         }
     }
 }
@@ -139,8 +348,243 @@ impl FromStr for Service {
     type Err = Error;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let postconfig = shellexpand::full(s)?.to_string();
-        toml::from_str::<Service>(&postconfig).map_err(Error::from)
+        Self::from_str_with_instance(s, None)
+    }
+}
+
+/// Templates the string values of a service config using the environment.
+/// `${INSTANCE}` is replaced with the instance id. If there is no instance id, the environment is
+/// used as for any other variable, and it's left untouched if missing.
+/// Commands are only templated with `${INSTANCE}`: their variables are expanded when the service
+/// is spawned, check `process_spawner::expand_args`.
+/// Returns whether any value has been templated.
+pub(crate) fn expand_config(config: &mut toml::Table, instance: Option<&str>) -> Result<bool> {
+    config
+        .iter_mut()
+        .try_fold(false, |templated, (key, value)| {
+            Ok(expand_value(value, &mut vec![key.clone()], instance)? || templated)
+        })
+}
+
+/// Templates `value`, found at `path` in a service config. Check `expand_config`.
+pub(crate) fn expand_value(
+    value: &mut toml::Value,
+    path: &mut Vec<String>,
+    instance: Option<&str>,
+) -> Result<bool> {
+    match value {
+        toml::Value::String(s) => {
+            let expanded = if is_command(path) {
+                match instance {
+                    Some(id) => expand_instance(s, id),
+                    None => s.clone(),
+                }
+            } else {
+                expand_str(s, instance)
+                    .with_context(|| format!("Failed templating `{}`", path.join(".")))?
+            };
+            let templated = expanded != *s;
+            *s = expanded;
+            Ok(templated)
+        }
+        toml::Value::Array(values) => values.iter_mut().try_fold(false, |templated, value| {
+            Ok(expand_value(value, path, instance)? || templated)
+        }),
+        toml::Value::Table(table) => table.iter_mut().try_fold(false, |templated, (key, value)| {
+            path.push(key.clone());
+            let res = expand_value(value, path, instance);
+            path.pop();
+            Ok(res? || templated)
+        }),
+        _ => Ok(false),
+    }
+}
+
+/// Whether the value at `path` is a command: the service's one, a hook's one or the reload one.
+fn is_command(path: &[String]) -> bool {
+    let path: Vec<&str> = path.iter().map(String::as_str).collect();
+    matches!(
+        path.as_slice(),
+        ["command", ..] | ["hooks", _, "command", ..] | ["reload", "command", ..]
+    )
+}
+
+/// Replaces `$INSTANCE` and `${INSTANCE}` in a command with the instance id. The other variables
+/// and the `$$` escapes are left to the expansion on spawn.
+fn expand_instance(command: &str, id: &str) -> String {
+    let context = |var: &str| (var == INSTANCE_VAR).then_some(id);
+    command
+        .split("$$")
+        .map(|part| shellexpand::env_with_context_no_errors(part, context))
+        .collect::<Vec<_>>()
+        .join("$$")
+}
+
+fn expand_str(s: &str, instance: Option<&str>) -> Result<String> {
+    let context = |var: &str| match (var, instance) {
+        (INSTANCE_VAR, Some(id)) => Ok(Some(id.to_string())),
+        (INSTANCE_VAR, None) => Ok(Some(
+            env::var(var).unwrap_or_else(|_| format!("${{{}}}", var)),
+        )),
+        _ => env::var(var).map(Some),
+    };
+    // Like `shellexpand::full`, `~` is the home of the user running Horust.
+    let home_dir = || {
+        env::var("HOME").ok().or_else(|| {
+            let user = unistd::User::from_uid(unistd::getuid()).ok()??;
+            Some(user.dir.display().to_string())
+        })
+    };
+    Ok(shellexpand::full_with_context(s, home_dir, context)?.into_owned())
+}
+
+/// The formats supported for service files, detected from the file extension.
+/// All of them share the same field names and templating.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ConfigFormat {
+    Toml,
+    Yaml,
+    Json,
+}
+
+impl ConfigFormat {
+    /// Returns the format for the file extension, if supported.
+    pub fn from_extension(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "toml" => Some(ConfigFormat::Toml),
+            "yaml" | "yml" => Some(ConfigFormat::Yaml),
+            "json" => Some(ConfigFormat::Json),
+            _ => None,
+        }
+    }
+
+    /// Like `from_extension`, but falls back to TOML for unknown extensions.
+    pub fn from_path(path: &Path) -> Self {
+        Self::from_extension(path).unwrap_or(ConfigFormat::Toml)
+    }
+
+    pub(crate) fn parse<T: DeserializeOwned>(self, s: &str) -> Result<T> {
+        Ok(match self {
+            ConfigFormat::Toml => toml::from_str(s)?,
+            ConfigFormat::Yaml => serde_yaml::from_str(s)?,
+            ConfigFormat::Json => serde_json::from_str(s)?,
+        })
+    }
+}
+
+/// Reads and templates the raw config in path.
+fn read_config(path: &Path, instance: Option<&str>) -> Result<toml::Table> {
+    let preconfig = std::fs::read_to_string(path)?;
+    let mut config: toml::Table = ConfigFormat::from_path(path)
+        .parse(&preconfig)
+        .with_context(|| format!("Failed parsing: {}", path.display()))?;
+    expand_config(&mut config, instance)?;
+    Ok(config)
+}
+
+/// Loads the base config `extends`-ed by the service file in path, with its own bases merged
+/// under it. `chain` holds the files loaded so far, used for detecting inheritance cycles.
+fn load_base_config(
+    path: &Path,
+    extends: toml::Value,
+    instance: Option<&str>,
+    chain: &mut Vec<PathBuf>,
+) -> Result<toml::Table> {
+    let base_path = match extends {
+        toml::Value::String(base) => path.parent().unwrap_or(Path::new("")).join(base),
+        value => anyhow::bail!("`{}` should be a file path, found: {}", EXTENDS_KEY, value),
+    };
+    let canonical = base_path
+        .canonicalize()
+        .with_context(|| format!("Failed loading base service: {}", base_path.display()))?;
+    if chain.contains(&canonical) {
+        let chain = chain
+            .iter()
+            .chain(std::iter::once(&canonical))
+            .map(|path| path.display().to_string())
+            .collect();
+        return Err(ValidationError::InheritanceCycle { chain }.into());
+    }
+    chain.push(canonical);
+    // Being abstract is not inherited.
+    let mut config = read_config(&base_path, instance)?;
+    config.remove(ABSTRACT_KEY);
+    match config.remove(EXTENDS_KEY) {
+        Some(extends) => {
+            let mut base = load_base_config(&base_path, extends, instance, chain)?;
+            merge_extended_config(&mut base, config);
+            Ok(base)
+        }
+        None => Ok(config),
+    }
+}
+
+/// Deep-merges the `fragment` config into `base`. Tables are merged key by key, lists are appended
+/// (an empty list resets them), and any other value is overridden.
+pub(crate) fn merge_config(base: &mut toml::Table, fragment: toml::Table) {
+    for (key, value) in fragment {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(base_table)), toml::Value::Table(table)) => {
+                merge_config(base_table, table)
+            }
+            (Some(toml::Value::Array(base_array)), toml::Value::Array(array))
+                if !array.is_empty() =>
+            {
+                base_array.extend(array)
+            }
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+/// Deep-merges the config of a service into the `base` it extends. Like `merge_config`, but lists
+/// are overridden: a service replaces the lists of its base.
+fn merge_extended_config(base: &mut toml::Table, config: toml::Table) {
+    for (key, value) in config {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(base_table)), toml::Value::Table(table)) => {
+                merge_extended_config(base_table, table)
+            }
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+/// Fills the values missing in `config` with the ones in `defaults`, recursing into tables.
+/// Unlike `merge_config`, the values already in config are never changed, lists included.
+pub(crate) fn merge_defaults(config: &mut toml::Table, defaults: &toml::Table) {
+    for (key, default) in defaults {
+        match (config.get_mut(key), default) {
+            (Some(toml::Value::Table(table)), toml::Value::Table(default_table)) => {
+                merge_defaults(table, default_table)
+            }
+            (Some(_), _) => (),
+            (None, default) => {
+                config.insert(key.clone(), default.clone());
+            }
+        }
+    }
+}
+
+/// The instances of a template service: either a list of ids or how many instances to create.
+/// With a count, instance ids will go from 1 to count.
+#[derive(Serialize, Clone, Deserialize, Debug, Eq, PartialEq)]
+#[serde(untagged)]
+pub enum Instances {
+    Count(u32),
+    Ids(Vec<String>),
+}
+
+impl Instances {
+    pub fn ids(&self) -> Vec<String> {
+        match self {
+            Instances::Count(count) => (1..=*count).map(|id| id.to_string()).collect(),
+            Instances::Ids(ids) => ids.clone(),
+        }
     }
 }
 
@@ -226,8 +670,20 @@ pub struct Environment {
     pub keep_env: bool,
     #[serde(default)]
     pub re_export: Vec<String>,
+    /// Glob patterns (`*` and `?`) of the variables to inherit from Horust's environment.
+    #[serde(default)]
+    pub inherit: Vec<String>,
+    /// Glob patterns of the inherited variables to remove.
+    #[serde(default)]
+    pub unset: Vec<String>,
     #[serde(default)]
     pub additional: HashMap<String, String>,
+    /// Dotenv files, read on every spawn.
+    #[serde(default)]
+    pub env_files: Vec<PathBuf>,
+    /// Variables whose value is the content of a file (e.g. a secret), read on every spawn.
+    #[serde(default)]
+    pub from_file: HashMap<String, PathBuf>,
 }
 
 impl Environment {
@@ -243,11 +699,20 @@ impl Environment {
 
     /// Create the environment K=V variables, used for exec into the new process.
     /// User defined environment variables overwrite the predefined variables.
-    pub(crate) fn get_environment(&self, user_name: String, user_home: String) -> Vec<String> {
-        let mut initial: HashMap<String, String> = self
-            .keep_env
-            .then(|| std::env::vars().collect())
-            .unwrap_or_default();
+    pub(crate) fn get_environment(
+        &self,
+        user_name: String,
+        user_home: String,
+        metadata: Vec<(String, String)>,
+    ) -> Result<Vec<String>> {
+        // Unset removes what was inherited, but not what is defined by Horust or the service itself.
+        let is_unset = |key: &String| self.unset.iter().any(|pattern| glob_match(pattern, key));
+        let mut initial: HashMap<String, String> = std::env::vars()
+            .filter(|(key, _)| {
+                self.keep_env || self.inherit.iter().any(|pattern| glob_match(pattern, key))
+            })
+            .filter(|(key, _)| !is_unset(key))
+            .collect();
 
         let mut additional = self.additional.clone();
 
@@ -267,6 +732,7 @@ impl Environment {
 
         let env: HashMap<String, String> = vec![hostname, path_env, user_name, user_home]
             .into_iter()
+            .chain(metadata)
             .collect();
         // The variables from env have always precedence over initial. E.g. home, and user might differ.
         initial.extend(env);
@@ -279,6 +745,7 @@ impl Environment {
         let re_export: HashMap<String, String> = self
             .re_export
             .iter()
+            .filter(|key| !is_unset(key))
             .filter_map(|key| {
                 std::env::var(key)
                     .map_err(|err| error!("Error getting env key: {}, error: {} ", key, err))
@@ -290,19 +757,127 @@ impl Environment {
         // If a variable is re_export, then it has precedence over initial + env.
         initial.extend(re_export);
 
-        // Finally, additional has the higher precedence:
+        // Env files are applied in order, so the last one wins.
+        for path in &self.env_files {
+            let content = std::fs::read_to_string(path)
+                .with_context(|| format!("Failed reading env file: {}", path.display()))?;
+            let vars = parse_env_file(&content)
+                .with_context(|| format!("Failed parsing env file: {}", path.display()))?;
+            initial.extend(vars);
+        }
+
+        // Then additional has the higher precedence:
         initial.into_iter().for_each(|(k, v)| {
             additional.entry(k).or_insert(v);
         });
 
+        // Values read from files are the most specific ones.
+        for (key, path) in &self.from_file {
+            let mut value = std::fs::read_to_string(path).with_context(|| {
+                format!(
+                    "Failed reading the value of {} from: {}",
+                    key,
+                    path.display()
+                )
+            })?;
+            if value.ends_with('\n') {
+                value.pop();
+                if value.ends_with('\r') {
+                    value.pop();
+                }
+            }
+            additional.insert(key.clone(), value);
+        }
+
         // This is the suitable format for `exec`
-        additional
+        Ok(additional
             .into_iter()
             .map(|(k, v)| format!("{}={}", k, v))
-            .collect()
+            .collect())
     }
 }
 
+/// Matches `text` against a glob pattern, where `*` matches any sequence of characters and `?`
+/// a single character.
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // Position of the last `*` in the pattern, and of the text it is matching from.
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, t));
+            p += 1;
+        } else if let Some((star_p, star_t)) = star {
+            // Backtrack: let the last `*` match one more character.
+            p = star_p + 1;
+            t = star_t + 1;
+            star = Some((star_p, star_t + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+/// Parses a dotenv file: `KEY=value` lines, with optional `export` prefix, `#` comments and
+/// quoted values. Errors only report the line number, as the values might be secrets.
+fn parse_env_file(content: &str) -> Result<Vec<(String, String)>> {
+    let mut vars = vec![];
+    for (idx, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line = line.strip_prefix("export ").unwrap_or(line);
+        let (key, value) = line
+            .split_once('=')
+            .with_context(|| format!("Line {}: expected `KEY=value`.", idx + 1))?;
+        let key = key.trim();
+        if key.is_empty() || key.contains(char::is_whitespace) {
+            anyhow::bail!("Line {}: invalid variable name.", idx + 1);
+        }
+        let value = value.trim();
+        let value = if let Some(quoted) = value.strip_prefix('"') {
+            let quoted = quoted
+                .strip_suffix('"')
+                .with_context(|| format!("Line {}: unterminated quoted value.", idx + 1))?;
+            let mut unescaped = String::with_capacity(quoted.len());
+            let mut chars = quoted.chars();
+            while let Some(c) = chars.next() {
+                if c != '\\' {
+                    unescaped.push(c);
+                    continue;
+                }
+                match chars.next() {
+                    Some('n') => unescaped.push('\n'),
+                    Some('t') => unescaped.push('\t'),
+                    Some(escaped) => unescaped.push(escaped),
+                    None => unescaped.push(c),
+                }
+            }
+            unescaped
+        } else if let Some(quoted) = value.strip_prefix('\'') {
+            quoted
+                .strip_suffix('\'')
+                .with_context(|| format!("Line {}: unterminated quoted value.", idx + 1))?
+                .to_string()
+        } else {
+            // Unquoted values can have a trailing comment.
+            match value.split_once(" #") {
+                Some((value, _comment)) => value.trim_end().to_string(),
+                None => value.to_string(),
+            }
+        };
+        vars.push((key.to_string(), value));
+    }
+    Ok(vars)
+}
+
 #[derive(Serialize, Clone, Deserialize, Debug, Eq, PartialEq)]
 #[serde(rename_all = "kebab-case", deny_unknown_fields)]
 pub struct Healthiness {
@@ -368,7 +943,7 @@ impl User {
         }
     }
 
-    fn get_raw_user(&self) -> Result<unistd::User> {
+    pub(crate) fn get_raw_user(&self) -> Result<unistd::User> {
         let uid = self.get_uid()?;
         let user =
             unistd::User::from_uid(uid)?.with_context(|| format!("User `{}` not found", uid))?;
@@ -384,6 +959,28 @@ impl User {
     }
 }
 
+/// A group in the system.
+/// It can be either a gid or a group name (available in /etc/group)
+#[derive(Serialize, Clone, Deserialize, Debug, Eq, PartialEq)]
+#[serde(untagged)]
+pub enum Group {
+    Gid(u32),
+    Name(String),
+}
+
+impl Group {
+    pub(crate) fn get_gid(&self) -> Result<unistd::Gid> {
+        match &self {
+            Group::Name(name) => {
+                let group = unistd::Group::from_name(name)?
+                    .with_context(|| format!("Group `{}` not found", name))?;
+                Ok(group.gid)
+            }
+            Group::Gid(gid) => Ok(unistd::Gid::from_raw(*gid)),
+        }
+    }
+}
+
 #[derive(Serialize, Clone, Deserialize, Debug, Eq, PartialEq, Hash, Default)]
 pub enum ServiceStatus {
     /// The service will be started asap
@@ -480,13 +1077,32 @@ impl From<&str> for RestartStrategy {
 pub struct Failure {
     #[serde(default = "Failure::default_successful_exit_code")]
     pub successful_exit_code: Vec<i32>,
+    /// Being killed by one of these signals is not a failure.
+    #[serde(default)]
+    pub successful_signals: Vec<TerminationSignal>,
+    #[serde(default = "Failure::default_strategy")]
     pub strategy: FailureStrategy,
 }
 
 impl Failure {
+    /// Whether the service terminated successfully.
+    pub fn is_successful(&self, reason: ExitReason) -> bool {
+        match reason {
+            ExitReason::Exited(code) => self.successful_exit_code.contains(&code),
+            ExitReason::Signaled(signal) | ExitReason::CoreDumped(signal) => self
+                .successful_signals
+                .iter()
+                .any(|successful| Signal::from(*successful) == signal),
+        }
+    }
+
     fn default_successful_exit_code() -> Vec<i32> {
         vec![0]
     }
+
+    fn default_strategy() -> FailureStrategy {
+        FailureStrategy::Ignore
+    }
 }
 
 #[derive(Serialize, Clone, Deserialize, Debug, Eq, PartialEq)]
@@ -501,7 +1117,8 @@ impl Default for Failure {
     fn default() -> Self {
         Failure {
             successful_exit_code: Self::default_successful_exit_code(),
-            strategy: FailureStrategy::Ignore,
+            successful_signals: Vec::new(),
+            strategy: Self::default_strategy(),
         }
     }
 }
@@ -535,12 +1152,35 @@ pub struct Termination {
     #[serde(default = "Vec::new")]
     // Will kill this service if any of the services in Vec are failed
     pub die_if_failed: Vec<ServiceName>,
+    #[serde(default)]
+    /// Which processes receive the termination signals.
+    pub kill_mode: KillMode,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    /// Signals to send in order, each followed by its wait. If set, `signal` and `wait` are ignored.
+    pub steps: Vec<TerminationStep>,
 }
 
 impl Termination {
     fn default_wait() -> Duration {
         Duration::from_secs(5)
     }
+
+    /// The termination sequence: the `steps`, or the single `signal` and `wait` if none is set.
+    pub(crate) fn steps(&self) -> Vec<TerminationStep> {
+        if self.steps.is_empty() {
+            vec![TerminationStep {
+                signal: self.signal,
+                wait: self.wait,
+            }]
+        } else {
+            self.steps.clone()
+        }
+    }
+
+    /// Time to wait from the first signal before SIGKILL.
+    pub(crate) fn total_wait(&self) -> Duration {
+        self.steps().iter().map(|step| step.wait).sum()
+    }
 }
 
 impl Default for Termination {
@@ -549,10 +1189,37 @@ impl Default for Termination {
             signal: Default::default(),
             wait: Self::default_wait(),
             die_if_failed: Vec::new(),
+            kill_mode: Default::default(),
+            steps: Vec::new(),
         }
     }
 }
 
+/// A step of the termination sequence of a service.
+#[derive(Serialize, Copy, Clone, Deserialize, Debug, Eq, PartialEq)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct TerminationStep {
+    #[serde(default)]
+    pub(crate) signal: TerminationSignal,
+    #[serde(default = "Termination::default_wait", with = "humantime_serde")]
+    /// Time to wait before the next step, or SIGKILL after the last one.
+    pub wait: Duration,
+}
+
+/// The processes of a service that are signalled on termination. With `process-group` and
+/// `cgroup`, the service has exited only once all of them are gone.
+#[derive(Serialize, Copy, Clone, Default, Deserialize, Debug, Eq, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum KillMode {
+    /// Only the main process.
+    #[default]
+    Process,
+    /// The process group of the main process, which is created on spawn.
+    ProcessGroup,
+    /// Every process in the `horust_<name>` cgroup of the service.
+    Cgroup,
+}
+
 #[derive(Serialize, Copy, Clone, Default, Deserialize, Debug, Eq, PartialEq)]
 #[allow(clippy::upper_case_acronyms)]
 pub enum TerminationSignal {
@@ -631,13 +1298,162 @@ impl From<TerminationSignal> for Signal {
     }
 }
 
+/// Commands run on the transitions of a service, with its user, environment and working directory.
+#[derive(Serialize, Clone, Default, Deserialize, Debug, Eq, PartialEq)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Hooks {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    /// Run before spawning the service, after the start delay.
+    pub pre_start: Option<Hook>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    /// Run once the service is running, i.e. it's healthy.
+    pub post_start: Option<Hook>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    /// Run before sending the termination signals.
+    pub pre_stop: Option<Hook>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    /// Run after the service has exited.
+    pub post_stop: Option<Hook>,
+}
+
+impl Hooks {
+    pub fn get(&self, kind: HookKind) -> Option<&Hook> {
+        match kind {
+            HookKind::PreStart => self.pre_start.as_ref(),
+            HookKind::PostStart => self.post_start.as_ref(),
+            HookKind::PreStop => self.pre_stop.as_ref(),
+            HookKind::PostStop => self.post_stop.as_ref(),
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Deserialize, Debug, Eq, PartialEq)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Hook {
+    /// Like the command of the service, but never run through a shell.
+    pub command: Command,
+    #[serde(default = "Hook::default_timeout", with = "humantime_serde")]
+    /// The hook is killed and considered failed if it runs for longer than this.
+    pub timeout: Duration,
+    #[serde(default)]
+    pub on_failure: HookFailure,
+}
+
+impl Hook {
+    fn default_timeout() -> Duration {
+        Duration::from_secs(30)
+    }
+}
+
+/// The transition a hook is run on.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum HookKind {
+    PreStart,
+    PostStart,
+    PreStop,
+    PostStop,
+}
+
+impl HookKind {
+    pub const ALL: [HookKind; 4] = [
+        HookKind::PreStart,
+        HookKind::PostStart,
+        HookKind::PreStop,
+        HookKind::PostStop,
+    ];
+}
+
+impl std::fmt::Display for HookKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            HookKind::PreStart => "pre-start",
+            HookKind::PostStart => "post-start",
+            HookKind::PreStop => "pre-stop",
+            HookKind::PostStop => "post-stop",
+        })
+    }
+}
+
+/// What a failed hook does.
+#[derive(Serialize, Copy, Clone, Default, Deserialize, Debug, Eq, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum HookFailure {
+    /// The failure is only logged.
+    #[default]
+    Ignore,
+    /// The start of the service fails. Only for `pre-start` and `post-start`.
+    FailStart,
+    /// The service is not stopped, and keeps running. Only for `pre-stop`.
+    AbortStop,
+}
+
+impl std::fmt::Display for HookFailure {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            HookFailure::Ignore => "ignore",
+            HookFailure::FailStart => "fail-start",
+            HookFailure::AbortStop => "abort-stop",
+        })
+    }
+}
+
+impl HookFailure {
+    fn is_allowed_for(self, kind: HookKind) -> bool {
+        match self {
+            HookFailure::Ignore => true,
+            HookFailure::FailStart => matches!(kind, HookKind::PreStart | HookKind::PostStart),
+            HookFailure::AbortStop => kind == HookKind::PreStop,
+        }
+    }
+}
+
+/// How the service is asked to reload its configuration, without being restarted.
+#[derive(Serialize, Clone, Deserialize, Debug, Eq, PartialEq)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Reload {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    /// Send this signal to the main process of the service.
+    pub signal: Option<TerminationSignal>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    /// Run this command, like a hook. Cannot be used together with `signal`.
+    pub command: Option<Command>,
+    #[serde(default = "Reload::default_timeout", with = "humantime_serde")]
+    /// The command is killed and the reload considered failed if it runs for longer than this.
+    pub timeout: Duration,
+}
+
+impl Reload {
+    fn default_timeout() -> Duration {
+        Duration::from_secs(30)
+    }
+
+    /// Whether the service can be reloaded.
+    pub fn is_defined(&self) -> bool {
+        self.signal.is_some() || self.command.is_some()
+    }
+}
+
+impl Default for Reload {
+    fn default() -> Self {
+        Self {
+            signal: None,
+            command: None,
+            timeout: Self::default_timeout(),
+        }
+    }
+}
+
 #[derive(Serialize, Clone, Deserialize, Debug, PartialEq)]
 #[serde(rename_all = "kebab-case", deny_unknown_fields)]
 pub struct ResourceLimit {
     #[serde(default)]
     /// The CPU time that the process can use
     pub(crate) cpu: Option<f64>,
-    #[serde(default, skip_serializing, deserialize_with = "str_to_optional_bytes")]
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "str_to_optional_bytes"
+    )]
     /// The maximum amount of memory that the process can use
     pub(crate) memory: Option<u64>,
     #[serde(default)]
@@ -664,19 +1480,14 @@ impl Default for ResourceLimit {
 impl Eq for ResourceLimit {}
 
 impl ResourceLimit {
-    pub(crate) fn apply(&self, name: &str, pid: unistd::Pid) -> anyhow::Result<()> {
-        if self.has_no_limit() {
+    /// Moves `pid` into the cgroup of the service and applies the limits. The cgroup is created
+    /// even without limits if the `kill_mode` needs it.
+    pub(crate) fn apply(&self, name: &str, pid: unistd::Pid, kill_mode: KillMode) -> Result<()> {
+        if self.has_no_limit() && kill_mode != KillMode::Cgroup {
             return Ok(());
         }
 
-        // has to be an absolute path for cgroups v2
-        let cgroup_path = Path::new(DEFAULT_CGROUP_ROOT).join(format!("horust_{}", name));
-        let manager = create_cgroup_manager(CgroupConfig {
-            cgroup_path: cgroup_path.to_path_buf(),
-            systemd_cgroup: false,
-            container_name: name.to_string(),
-        })
-        .with_context(|| format!("Failed to create cgroup manager for {}", name))?;
+        let manager = cgroup_manager(name)?;
         let mut resource = LinuxResources::default();
         if let Some(cpu) = self.cpu {
             let cpu = LinuxCpuBuilder::default()
@@ -710,16 +1521,445 @@ impl ResourceLimit {
     }
 }
 
+fn cgroup_manager(name: &str) -> Result<AnyCgroupManager> {
+    // has to be an absolute path for cgroups v2
+    let cgroup_path = Path::new(DEFAULT_CGROUP_ROOT).join(format!("horust_{}", name));
+    create_cgroup_manager(CgroupConfig {
+        cgroup_path: cgroup_path.to_path_buf(),
+        systemd_cgroup: false,
+        container_name: name.to_string(),
+    })
+    .with_context(|| format!("Failed to create cgroup manager for {}", name))
+}
+
+/// The pids in the cgroup of the service.
+pub(crate) fn cgroup_pids(name: &str) -> Result<Vec<unistd::Pid>> {
+    cgroup_manager(name)?
+        .get_all_pids()
+        .with_context(|| format!("Failed to read the pids of cgroup {}", name))
+}
+
+/// The POSIX resource limits of the service, set with `setrlimit` before running it. Unlike the
+/// `resource-limit`, they don't need any cgroup permission.
+#[derive(Serialize, Clone, Default, Deserialize, Debug, Eq, PartialEq)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Rlimits {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    /// The maximum number of open file descriptors.
+    pub nofile: Option<Rlimit>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    /// The maximum number of processes of the user.
+    pub nproc: Option<Rlimit>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    /// The maximum size of a core dump.
+    pub core: Option<Rlimit>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    /// The maximum amount of memory that can be locked.
+    pub memlock: Option<Rlimit>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    /// The maximum size of the stack.
+    pub stack: Option<Rlimit>,
+    #[serde(default, rename = "as", skip_serializing_if = "Option::is_none")]
+    /// The maximum size of the virtual memory.
+    pub address_space: Option<Rlimit>,
+}
+
+impl Rlimits {
+    /// The limits by resource, with their name in the config. It doesn't allocate, so it's safe
+    /// to use between `fork` and `exec`.
+    pub(crate) fn all(&self) -> [(Resource, &'static str, Option<Rlimit>); 6] {
+        [
+            (Resource::RLIMIT_NOFILE, "nofile", self.nofile),
+            (Resource::RLIMIT_NPROC, "nproc", self.nproc),
+            (Resource::RLIMIT_CORE, "core", self.core),
+            (Resource::RLIMIT_MEMLOCK, "memlock", self.memlock),
+            (Resource::RLIMIT_STACK, "stack", self.stack),
+            (Resource::RLIMIT_AS, "as", self.address_space),
+        ]
+    }
+}
+
+/// The soft and hard limits of a resource. A single value sets both of them.
+#[derive(Serialize, Clone, Copy, Deserialize, Debug, Eq, PartialEq)]
+#[serde(from = "RlimitConfig", into = "RlimitConfig")]
+pub struct Rlimit {
+    pub soft: RlimitValue,
+    pub hard: RlimitValue,
+}
+
+#[derive(Serialize, Clone, Deserialize)]
+#[serde(untagged, deny_unknown_fields)]
+enum RlimitConfig {
+    Both(RlimitValue),
+    Pair {
+        soft: RlimitValue,
+        hard: RlimitValue,
+    },
+}
+
+impl From<RlimitConfig> for Rlimit {
+    fn from(config: RlimitConfig) -> Self {
+        match config {
+            RlimitConfig::Both(value) => Rlimit {
+                soft: value,
+                hard: value,
+            },
+            RlimitConfig::Pair { soft, hard } => Rlimit { soft, hard },
+        }
+    }
+}
+
+impl From<Rlimit> for RlimitConfig {
+    fn from(rlimit: Rlimit) -> Self {
+        if rlimit.soft == rlimit.hard {
+            RlimitConfig::Both(rlimit.soft)
+        } else {
+            RlimitConfig::Pair {
+                soft: rlimit.soft,
+                hard: rlimit.hard,
+            }
+        }
+    }
+}
+
+/// A number, a size like "8 MiB", or "unlimited".
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum RlimitValue {
+    Limited(u64),
+    Unlimited,
+}
+
+impl RlimitValue {
+    pub(crate) fn raw(self) -> rlim_t {
+        match self {
+            RlimitValue::Limited(value) => value as rlim_t,
+            RlimitValue::Unlimited => RLIM_INFINITY,
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for RlimitValue {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        match ByteSize::deserialize(deserializer)? {
+            ByteSize::Text(text) if text == "unlimited" => Ok(RlimitValue::Unlimited),
+            size => size.bytes().map(RlimitValue::Limited),
+        }
+    }
+}
+
+impl Serialize for RlimitValue {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match self {
+            RlimitValue::Limited(value) => serializer.serialize_u64(*value),
+            RlimitValue::Unlimited => serializer.serialize_str("unlimited"),
+        }
+    }
+}
+
+/// Scheduling attributes and umask of the service, set before running it. If unset, they are
+/// inherited from Horust.
+#[derive(Serialize, Clone, Default, Deserialize, Debug, Eq, PartialEq)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct ProcessAttributes {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    /// From -20 (highest priority) to 19 (lowest).
+    pub nice: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub io_class: Option<IoClass>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    /// From 0 (highest priority) to 7 (lowest), within the `io_class`. Defaults to 4.
+    pub io_priority: Option<u8>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    /// The CPUs the service can run on.
+    pub cpu_affinity: Vec<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    /// From -1000 (never killed) to 1000 (killed first) by the OOM killer.
+    pub oom_score_adj: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub umask: Option<Umask>,
+}
+
+impl ProcessAttributes {
+    /// The I/O scheduling class and priority. Setting only the priority uses `best-effort`.
+    pub(crate) fn io_priority(&self) -> Option<(IoClass, u8)> {
+        match (self.io_class, self.io_priority) {
+            (None, None) => None,
+            (class, priority) => Some((class.unwrap_or_default(), priority.unwrap_or(4))),
+        }
+    }
+
+    /// The attributes out of their range, as (attribute, value, min, max).
+    fn out_of_range(&self) -> Vec<(&'static str, i64, i64, i64)> {
+        let max_cpu = nix::sched::CpuSet::count() as i64 - 1;
+        let ranges = [
+            ("nice", self.nice.map(i64::from), -20, 19),
+            ("io-priority", self.io_priority.map(i64::from), 0, 7),
+            (
+                "oom-score-adj",
+                self.oom_score_adj.map(i64::from),
+                -1000,
+                1000,
+            ),
+        ];
+        ranges
+            .into_iter()
+            .filter_map(|(name, value, min, max)| Some((name, value?, min, max)))
+            .chain(
+                self.cpu_affinity
+                    .iter()
+                    .map(|cpu| ("cpu-affinity", *cpu as i64, 0, max_cpu)),
+            )
+            .filter(|(_, value, min, max)| value < min || value > max)
+            .collect()
+    }
+}
+
+/// The I/O scheduling class, like in `ionice`.
+#[derive(Serialize, Copy, Clone, Default, Deserialize, Debug, Eq, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum IoClass {
+    /// Always served first: it needs root.
+    Realtime,
+    #[default]
+    BestEffort,
+    /// Served only when no other process needs the disk.
+    Idle,
+}
+
+impl IoClass {
+    /// The value of the class for `ioprio_set`.
+    pub(crate) fn raw(self) -> i32 {
+        match self {
+            IoClass::Realtime => 1,
+            IoClass::BestEffort => 2,
+            IoClass::Idle => 3,
+        }
+    }
+}
+
+/// A file mode creation mask: an octal string like "027", or a number.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Umask(pub u32);
+
+impl<'de> Deserialize<'de> for Umask {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum UmaskConfig {
+            Number(u32),
+            Octal(String),
+        }
+        let mask = match UmaskConfig::deserialize(deserializer)? {
+            UmaskConfig::Number(mask) => mask,
+            UmaskConfig::Octal(mask) => u32::from_str_radix(&mask, 8).map_err(|_| {
+                de::Error::custom(format!("umask `{}` is not an octal number", mask))
+            })?,
+        };
+        if mask > 0o777 {
+            return Err(de::Error::custom(format!("umask {:o} is above 777", mask)));
+        }
+        Ok(Umask(mask))
+    }
+}
+
+impl Serialize for Umask {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&format!("{:04o}", self.0))
+    }
+}
+
+/// The Linux namespaces the service runs in, isolated from Horust and the other services.
+#[derive(Serialize, Clone, Default, Deserialize, Debug, Eq, PartialEq)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Sandbox {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    /// The namespaces created for the service.
+    pub namespaces: Vec<Namespace>,
+    #[serde(default)]
+    /// Mount an empty tmpfs on /tmp.
+    pub private_tmp: bool,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    /// Bind mounted read-only on themselves.
+    pub read_only_paths: Vec<PathBuf>,
+    #[serde(default)]
+    /// Mount an empty read-only tmpfs on /home.
+    pub hide_home: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    /// The hostname in the `uts` namespace.
+    pub hostname: Option<String>,
+}
+
+impl Sandbox {
+    fn has_mounts(&self) -> bool {
+        self.private_tmp || self.hide_home || !self.read_only_paths.is_empty()
+    }
+
+    /// The flags to create the namespaces with. The mounts, and the new `/proc` of the `pid`
+    /// namespace, imply the `mount` namespace.
+    pub(crate) fn clone_flags(&self) -> CloneFlags {
+        let flags = self
+            .namespaces
+            .iter()
+            .fold(CloneFlags::empty(), |flags, namespace| {
+                flags | namespace.clone_flag()
+            });
+        if self.has_mounts() || flags.contains(CloneFlags::CLONE_NEWPID) {
+            flags | CloneFlags::CLONE_NEWNS
+        } else {
+            flags
+        }
+    }
+}
+
+#[derive(Serialize, Copy, Clone, Deserialize, Debug, Eq, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Namespace {
+    /// Mounts made by the service are not seen outside.
+    Mount,
+    /// The service is the init process of its own processes tree.
+    Pid,
+    /// Only a loopback interface is available.
+    Network,
+    Ipc,
+    /// Hostname and domain name.
+    Uts,
+}
+
+impl Namespace {
+    fn clone_flag(self) -> CloneFlags {
+        match self {
+            Namespace::Mount => CloneFlags::CLONE_NEWNS,
+            Namespace::Pid => CloneFlags::CLONE_NEWPID,
+            Namespace::Network => CloneFlags::CLONE_NEWNET,
+            Namespace::Ipc => CloneFlags::CLONE_NEWIPC,
+            Namespace::Uts => CloneFlags::CLONE_NEWUTS,
+        }
+    }
+}
+
 /// Runs some validation checks on the services.
 /// TODO: if redirect output is file, check it exists and permissions.
 pub fn validate(services: Vec<Service>) -> Result<Vec<Service>, ValidationErrors> {
     let mut errors = vec![];
+    let mut seen_names = HashSet::new();
+    let mut duplicated_names = HashSet::new();
     services.iter().for_each(|service| {
+        if !seen_names.insert(service.name.as_str())
+            && duplicated_names.insert(service.name.as_str())
+        {
+            errors.push(ValidationError::DuplicateName {
+                service: service.name.clone(),
+            });
+        }
         if service.command.is_empty() {
             errors.push(ValidationError::CommandEmpty {
                 service: service.name.clone(),
             });
         }
+        // Exit codes are never negative: -137 used to mean killed by SIGKILL.
+        service
+            .failure
+            .successful_exit_code
+            .iter()
+            .filter(|code| **code < 0)
+            .for_each(|code| {
+                errors.push(ValidationError::NegativeSuccessfulExitCode {
+                    service: service.name.clone(),
+                    code: *code,
+                })
+            });
+        if service.shell && matches!(service.command, Command::Args(_)) {
+            errors.push(ValidationError::ShellWithArgs {
+                service: service.name.clone(),
+            });
+        }
+        HookKind::ALL.into_iter().for_each(|kind| {
+            let Some(hook) = service.hooks.get(kind) else {
+                return;
+            };
+            if hook.command.is_empty() {
+                errors.push(ValidationError::HookCommandEmpty {
+                    service: service.name.clone(),
+                    hook: kind,
+                });
+            }
+            if !hook.on_failure.is_allowed_for(kind) {
+                errors.push(ValidationError::HookFailurePolicy {
+                    service: service.name.clone(),
+                    hook: kind,
+                    policy: hook.on_failure,
+                });
+            }
+        });
+        match &service.reload {
+            Reload {
+                signal: Some(_),
+                command: Some(_),
+                ..
+            } => errors.push(ValidationError::ReloadSignalAndCommand {
+                service: service.name.clone(),
+            }),
+            Reload {
+                command: Some(command),
+                ..
+            } if command.is_empty() => errors.push(ValidationError::ReloadCommandEmpty {
+                service: service.name.clone(),
+            }),
+            _ => (),
+        }
+        service
+            .rlimits
+            .all()
+            .into_iter()
+            .filter_map(|(_, name, rlimit)| Some((name, rlimit?)))
+            .filter(|(_, rlimit)| rlimit.soft.raw() > rlimit.hard.raw())
+            .for_each(|(name, _)| {
+                errors.push(ValidationError::RlimitSoftAboveHard {
+                    service: service.name.clone(),
+                    resource: name,
+                })
+            });
+        service
+            .process
+            .out_of_range()
+            .into_iter()
+            .for_each(|(attribute, value, min, max)| {
+                errors.push(ValidationError::ProcessAttributeOutOfRange {
+                    service: service.name.clone(),
+                    attribute,
+                    value,
+                    min,
+                    max,
+                })
+            });
+        let sandbox = &service.sandbox;
+        if sandbox.hostname.is_some() && !sandbox.namespaces.contains(&Namespace::Uts) {
+            errors.push(ValidationError::SandboxHostnameWithoutUts {
+                service: service.name.clone(),
+            });
+        }
+        sandbox
+            .read_only_paths
+            .iter()
+            .filter(|path| !path.is_absolute())
+            .for_each(|path| {
+                errors.push(ValidationError::SandboxRelativePath {
+                    service: service.name.clone(),
+                    path: path.clone(),
+                })
+            });
         if !service.start_after.is_empty() {
             debug!(
                 "Checking if all dependencies of '{}' exists, deps: {:?}",
@@ -727,6 +1967,12 @@ pub fn validate(services: Vec<Service>) -> Result<Vec<Service>, ValidationErrors
             );
         }
         service.start_after.iter().for_each(|name| {
+            if *name == service.name {
+                errors.push(ValidationError::SelfDependency {
+                    service: service.name.clone(),
+                });
+                return;
+            }
             let passed = services.iter().any(|s| s.name == *name);
             if !passed {
                 errors.push(ValidationError::MissingDependency {
@@ -735,7 +1981,23 @@ pub fn validate(services: Vec<Service>) -> Result<Vec<Service>, ValidationErrors
                 });
             }
         });
+        service
+            .termination
+            .die_if_failed
+            .iter()
+            .filter(|name| !services.iter().any(|s| s.name == **name))
+            .for_each(|name| {
+                errors.push(ValidationError::MissingDieIfFailed {
+                    service: service.name.clone(),
+                    target: name.clone(),
+                });
+            });
     });
+    errors.extend(
+        find_dependency_cycles(&services)
+            .into_iter()
+            .map(|cycle| ValidationError::DependencyCycle { cycle }),
+    );
     if errors.is_empty() {
         Ok(services)
     } else {
@@ -743,23 +2005,97 @@ pub fn validate(services: Vec<Service>) -> Result<Vec<Service>, ValidationErrors
     }
 }
 
+/// Walks the `start-after` graph and returns every cycle found, as a path like `[a, b, a]`.
+/// Self dependencies and missing services are reported on their own by `validate`, so they're
+/// skipped here.
+fn find_dependency_cycles(services: &[Service]) -> Vec<Vec<ServiceName>> {
+    #[derive(Clone, Copy, PartialEq, Eq)]
+    enum Mark {
+        Visiting,
+        Visited,
+    }
+
+    fn visit<'a>(
+        name: &'a str,
+        graph: &HashMap<&'a str, &'a Service>,
+        marks: &mut HashMap<&'a str, Mark>,
+        path: &mut Vec<&'a str>,
+        cycles: &mut Vec<Vec<ServiceName>>,
+    ) {
+        match marks.get(name) {
+            Some(Mark::Visited) => return,
+            Some(Mark::Visiting) => {
+                let start = path.iter().position(|n| *n == name).unwrap();
+                let mut cycle: Vec<ServiceName> =
+                    path[start..].iter().map(|n| n.to_string()).collect();
+                cycle.push(name.to_string());
+                cycles.push(cycle);
+                return;
+            }
+            None => (),
+        }
+        marks.insert(name, Mark::Visiting);
+        path.push(name);
+        let service: &'a Service = graph[name];
+        service
+            .start_after
+            .iter()
+            .filter(|dep| *dep != name && graph.contains_key(dep.as_str()))
+            .for_each(|dep| visit(dep, graph, marks, path, cycles));
+        path.pop();
+        marks.insert(name, Mark::Visited);
+    }
+
+    let mut graph: HashMap<&str, &Service> = HashMap::new();
+    services.iter().for_each(|service| {
+        graph.entry(service.name.as_str()).or_insert(service);
+    });
+    let mut marks = HashMap::new();
+    let mut cycles = vec![];
+    services.iter().for_each(|service| {
+        visit(
+            service.name.as_str(),
+            &graph,
+            &mut marks,
+            &mut vec![],
+            &mut cycles,
+        )
+    });
+    cycles
+}
+
+/// A size in bytes: either a human readable string like "100MB", or a plain number of bytes.
+/// Sizes are serialized as plain numbers, so they can be read back without any loss.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ByteSize {
+    Bytes(u64),
+    Text(String),
+}
+
+impl ByteSize {
+    fn bytes<E: de::Error>(self) -> Result<u64, E> {
+        match self {
+            ByteSize::Bytes(bytes) => Ok(bytes),
+            ByteSize::Text(s) => bytefmt::parse(s).map_err(de::Error::custom),
+        }
+    }
+}
+
 fn str_to_bytes<'de, D>(deserializer: D) -> Result<u64, D::Error>
 where
     D: Deserializer<'de>,
 {
-    let s = String::deserialize(deserializer)?;
-    bytefmt::parse(s).map_err(de::Error::custom)
+    ByteSize::deserialize(deserializer)?.bytes()
 }
 
 fn str_to_optional_bytes<'de, D>(deserializer: D) -> Result<Option<u64>, D::Error>
 where
     D: Deserializer<'de>,
 {
-    let s = Option::<String>::deserialize(deserializer)?;
-    match s {
-        Some(s) => bytefmt::parse(s).map(Some).map_err(de::Error::custom),
-        None => Ok(None),
-    }
+    Option::<ByteSize>::deserialize(deserializer)?
+        .map(ByteSize::bytes)
+        .transpose()
 }
 
 #[cfg(test)]
@@ -767,10 +2103,15 @@ mod test {
     use std::str::FromStr;
     use std::time::Duration;
 
+    use nix::sched::CloneFlags;
+
     use crate::horust::formats::ResourceLimit;
     use crate::horust::formats::{
-        validate, Environment, Failure, FailureStrategy, Healthiness, Restart, RestartStrategy,
-        Service, Termination, TerminationSignal::TERM,
+        validate, Command, Environment, Failure, FailureStrategy, Group, Healthiness, Hook,
+        HookFailure, Hooks, IoClass, KillMode, Namespace, ProcessAttributes, Reload, Restart,
+        RestartStrategy, Rlimit, RlimitValue, Rlimits, Sandbox, Service, Termination,
+        TerminationSignal::{HUP, TERM},
+        Umask,
     };
     use crate::horust::get_sample_service;
 
@@ -793,14 +2134,25 @@ mod test {
         let current_user_name: String = super::User::default().get_name().unwrap();
         let expected = Service {
             name: "".to_string(),
-            command: "/bin/bash -c \'echo hello world\'".to_string(),
+            instances: None,
+            instance: None,
+            command: Command::Line("/bin/bash -c \'echo hello world\'".to_string()),
+            shell: false,
             user: super::User::Name(current_user_name),
+            group: None,
+            supplementary_groups: None,
             environment: Environment {
                 keep_env: false,
                 re_export: vec!["PATH".to_string(), "DB_PASS".to_string()],
+                inherit: vec!["LANG".to_string(), "AWS_*".to_string()],
+                unset: vec!["*_TOKEN".to_string()],
                 additional: vec![("key".to_string(), "value".to_string())]
                     .into_iter()
                     .collect(),
+                env_files: vec!["/run/secrets/app.env".into()],
+                from_file: vec![("DB_PASS".to_string(), "/run/secrets/db_pass".into())]
+                    .into_iter()
+                    .collect(),
             },
             working_directory: "/tmp/".into(),
             stdout: "/var/logs/hello_world_svc/stdout.log".into(),
@@ -823,18 +2175,82 @@ mod test {
             signal_rewrite: None,
             failure: Failure {
                 successful_exit_code: vec![0, 1, 255],
+                successful_signals: vec![TERM],
                 strategy: FailureStrategy::Ignore,
             },
             termination: Termination {
                 signal: TERM,
                 wait: Duration::from_secs(10),
-                die_if_failed: vec!["db.toml".into()],
+                die_if_failed: vec!["database".into()],
+                kill_mode: KillMode::ProcessGroup,
+                steps: vec![],
+            },
+            hooks: Hooks {
+                pre_start: Some(Hook {
+                    command: Command::Line("/usr/bin/migrate".into()),
+                    timeout: Duration::from_secs(60),
+                    on_failure: HookFailure::FailStart,
+                }),
+                post_start: Some(Hook {
+                    command: Command::Args(vec![
+                        "/usr/bin/register".into(),
+                        "--service".into(),
+                        "backend".into(),
+                    ]),
+                    timeout: Duration::from_secs(30),
+                    on_failure: HookFailure::Ignore,
+                }),
+                pre_stop: Some(Hook {
+                    command: Command::Line("/usr/bin/drain".into()),
+                    timeout: Duration::from_secs(30),
+                    on_failure: HookFailure::AbortStop,
+                }),
+                post_stop: Some(Hook {
+                    command: Command::Line("rm -f /tmp/backend.sock".into()),
+                    timeout: Duration::from_secs(30),
+                    on_failure: HookFailure::Ignore,
+                }),
+            },
+            reload: Reload {
+                signal: Some(HUP),
+                command: None,
+                timeout: Duration::from_secs(10),
             },
             resource_limit: ResourceLimit {
                 cpu: Some(0.5),
                 memory: Some(100 * 1024 * 1024),
                 pids_max: Some(100),
             },
+            rlimits: Rlimits {
+                nofile: Some(Rlimit {
+                    soft: RlimitValue::Limited(1024),
+                    hard: RlimitValue::Limited(4096),
+                }),
+                core: Some(Rlimit {
+                    soft: RlimitValue::Unlimited,
+                    hard: RlimitValue::Unlimited,
+                }),
+                stack: Some(Rlimit {
+                    soft: RlimitValue::Limited(8 * 1024 * 1024),
+                    hard: RlimitValue::Limited(8 * 1024 * 1024),
+                }),
+                ..Default::default()
+            },
+            process: ProcessAttributes {
+                nice: Some(10),
+                io_class: Some(IoClass::BestEffort),
+                io_priority: Some(6),
+                cpu_affinity: vec![0, 1],
+                oom_score_adj: Some(500),
+                umask: Some(Umask(0o027)),
+            },
+            sandbox: Sandbox {
+                namespaces: vec![Namespace::Pid, Namespace::Network, Namespace::Uts],
+                private_tmp: true,
+                read_only_paths: vec!["/etc".into(), "/usr".into()],
+                hide_home: true,
+                hostname: Some("sidecar".into()),
+            },
         };
 
         let service =
@@ -842,6 +2258,242 @@ mod test {
         assert_eq!(expected, service);
     }
 
+    #[test]
+    fn test_group() {
+        let service = Service::from_str(
+            r#"command = "app"
+group = "www-data"
+supplementary-groups = ["ssl-cert", 1001]"#,
+        )
+        .unwrap();
+        assert_eq!(service.group, Some(Group::Name("www-data".into())));
+        assert_eq!(
+            service.supplementary_groups,
+            Some(vec![Group::Name("ssl-cert".into()), Group::Gid(1001)])
+        );
+        let service = Service::from_str(r#"command = "app""#).unwrap();
+        assert_eq!(service.group, None);
+        assert_eq!(service.supplementary_groups, None);
+    }
+
+    #[test]
+    fn test_process_attributes() {
+        let process: ProcessAttributes = toml::from_str("umask = 0o022").unwrap();
+        assert_eq!(process.umask, Some(Umask(0o022)));
+        assert_eq!(process.io_priority(), None);
+        let process: ProcessAttributes = toml::from_str("io-priority = 2").unwrap();
+        assert_eq!(process.io_priority(), Some((IoClass::BestEffort, 2)));
+        let process: ProcessAttributes = toml::from_str(r#"io-class = "idle""#).unwrap();
+        assert_eq!(process.io_priority(), Some((IoClass::Idle, 4)));
+        assert!(toml::from_str::<ProcessAttributes>(r#"umask = "089""#).is_err());
+        assert!(toml::from_str::<ProcessAttributes>(r#"umask = "1777""#).is_err());
+    }
+
+    #[test]
+    fn test_sandbox() {
+        let sandbox: Sandbox = toml::from_str(r#"namespaces = ["uts"]"#).unwrap();
+        assert_eq!(sandbox.clone_flags(), CloneFlags::CLONE_NEWUTS);
+        let sandbox: Sandbox = toml::from_str(r#"private-tmp = true"#).unwrap();
+        assert_eq!(sandbox.clone_flags(), CloneFlags::CLONE_NEWNS);
+        let sandbox: Sandbox = toml::from_str(r#"namespaces = ["pid"]"#).unwrap();
+        assert_eq!(
+            sandbox.clone_flags(),
+            CloneFlags::CLONE_NEWPID | CloneFlags::CLONE_NEWNS
+        );
+        assert!(Sandbox::default().clone_flags().is_empty());
+
+        let mut service = Service::from_name("a");
+        service.sandbox.hostname = Some("a".into());
+        service.sandbox.read_only_paths = vec!["etc".into()];
+        let err = validate(vec![service]).unwrap_err().to_string();
+        assert!(err.contains("uts"), "{}", err);
+        assert!(err.contains("path etc "), "{}", err);
+    }
+
+    #[test]
+    fn test_serialization_round_trip() {
+        let service = Service::from_str(get_sample_service()).unwrap();
+        let toml = toml::to_string(&service).unwrap();
+        assert_eq!(service, Service::from_str(&toml).unwrap());
+        let json = serde_json::to_string(&service).unwrap();
+        assert_eq!(service, serde_json::from_str(&json).unwrap());
+    }
+
+    #[test]
+    fn test_command_templating() {
+        std::env::set_var("HORUST_TEST_TEMPLATING", "templated");
+        let service = r#"command = "app $PORT $$ ${INSTANCE} $INSTANCE $$INSTANCE $INSTANCES"
+stdout = "/tmp/${HORUST_TEST_TEMPLATING}-${INSTANCE}.log"
+[hooks]
+pre-start = { command = ["migrate", "${HORUST_TEST_TEMPLATING}"] }
+[reload]
+command = "app reload $$"
+"#;
+        // Commands are expanded on spawn, only the instance id is templated when loading them.
+        let service = Service::from_str_with_instance(service, Some("a")).unwrap();
+        assert_eq!(
+            service.command,
+            Command::Line("app $PORT $$ a a $$INSTANCE $INSTANCES".into())
+        );
+        assert_eq!(service.stdout, "/tmp/templated-a.log".into());
+        assert_eq!(
+            service.hooks.pre_start.unwrap().command,
+            Command::Args(vec!["migrate".into(), "${HORUST_TEST_TEMPLATING}".into()])
+        );
+        assert_eq!(
+            service.reload.command,
+            Some(Command::Line("app reload $$".into()))
+        );
+        let err = Service::from_str(r#"stdout = "/tmp/${HORUST_TEST_MISSING}.log""#).unwrap_err();
+        assert!(format!("{:#}", err).contains("`stdout`"), "{:#}", err);
+    }
+
+    #[test]
+    fn test_templating_home() {
+        let home = std::env::var("HOME").unwrap();
+        let service = r#"command = "app"
+working-directory = "~/app"
+stdout = "~/log"
+"#;
+        let service = Service::from_str(service).unwrap();
+        assert_eq!(
+            service.working_directory,
+            std::path::Path::new(&home).join("app")
+        );
+        assert_eq!(service.stdout, format!("{}/log", home).into());
+    }
+
+    #[test]
+    fn test_templating_only_strings() {
+        // Only string values are templated: a variable can't be used as a number or a boolean.
+        std::env::set_var("HORUST_TEST_RETRIES", "3");
+        let service = "command = \"app\"\n[restart]\nattempts = ";
+        Service::from_str(&format!("{}${{HORUST_TEST_RETRIES}}", service)).unwrap_err();
+        Service::from_str(&format!("{}\"${{HORUST_TEST_RETRIES}}\"", service)).unwrap_err();
+        Service::from_str(&format!("{}3", service)).unwrap();
+    }
+
+    #[test]
+    fn test_command() {
+        let service = Service::from_str(r#"command = "app --name 'hello world'""#).unwrap();
+        assert_eq!(
+            service.command.args(false).unwrap(),
+            vec!["app", "--name", "hello world"]
+        );
+        assert_eq!(
+            service.command.args(true).unwrap(),
+            vec!["/bin/sh", "-c", "app --name 'hello world'"]
+        );
+
+        let service = Service::from_str(r#"command = ["app", "--name", "hello world"]"#).unwrap();
+        assert_eq!(
+            service.command,
+            Command::Args(vec!["app".into(), "--name".into(), "hello world".into()])
+        );
+        assert_eq!(service.command.to_string(), "app --name 'hello world'");
+        service.command.args(true).unwrap_err();
+
+        assert!(Command::Args(vec![]).is_empty());
+        assert!(Command::Line(" ".into()).is_empty());
+        let mut service = Service::from_name("a");
+        service.command = Command::Args(vec!["app".into()]);
+        service.shell = true;
+        let err = validate(vec![service]).unwrap_err().to_string();
+        assert!(err.contains("`shell`"), "{}", err);
+    }
+
+    #[test]
+    fn test_failure_is_successful() {
+        use crate::horust::formats::ExitReason;
+        use nix::sys::signal::Signal;
+
+        let failure = Failure {
+            successful_exit_code: vec![0, 2],
+            successful_signals: vec![TERM],
+            ..Default::default()
+        };
+        assert!(failure.is_successful(ExitReason::Exited(2)));
+        assert!(!failure.is_successful(ExitReason::Exited(1)));
+        assert!(failure.is_successful(ExitReason::Signaled(Signal::SIGTERM)));
+        assert!(!failure.is_successful(ExitReason::Signaled(Signal::SIGKILL)));
+        assert!(!failure.is_successful(ExitReason::CoreDumped(Signal::SIGSEGV)));
+        assert!(!Failure::default().is_successful(ExitReason::Signaled(Signal::SIGTERM)));
+        assert_eq!(
+            ExitReason::CoreDumped(Signal::SIGSEGV).to_string(),
+            "killed by signal SIGSEGV (core dumped)"
+        );
+    }
+
+    #[test]
+    fn test_glob_match() {
+        use super::glob_match;
+        assert!(glob_match("LANG", "LANG"));
+        assert!(!glob_match("LANG", "LANGUAGE"));
+        assert!(glob_match("AWS_*", "AWS_REGION"));
+        assert!(glob_match("AWS_*", "AWS_"));
+        assert!(!glob_match("AWS_*", "MY_AWS_REGION"));
+        assert!(glob_match("*_TOKEN", "GITHUB_TOKEN"));
+        assert!(glob_match("*_TOKEN*", "A_TOKEN_B_TOKEN_C"));
+        assert!(glob_match("LC_?", "LC_A"));
+        assert!(!glob_match("LC_?", "LC_AB"));
+        assert!(glob_match("*", ""));
+    }
+
+    #[test]
+    fn test_env_files_and_from_file() {
+        let tempdir = tempfile::TempDir::with_prefix("env-files").unwrap();
+        let env_file = tempdir.path().join("app.env");
+        std::fs::write(
+            &env_file,
+            r#"# a comment
+export A=plain # with a comment
+B="quoted \"value\"\nnext line"
+C='single # quoted'
+
+D=overridden
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            super::parse_env_file(&std::fs::read_to_string(&env_file).unwrap()).unwrap(),
+            vec![
+                ("A".to_string(), "plain".to_string()),
+                ("B".to_string(), "quoted \"value\"\nnext line".to_string()),
+                ("C".to_string(), "single # quoted".to_string()),
+                ("D".to_string(), "overridden".to_string()),
+            ]
+        );
+        super::parse_env_file("A=ok\nnot a variable").unwrap_err();
+
+        let secret = tempdir.path().join("secret");
+        std::fs::write(&secret, "s3cr3t\n").unwrap();
+        let environment = Environment {
+            env_files: vec![env_file],
+            from_file: vec![("D".to_string(), secret.clone())]
+                .into_iter()
+                .collect(),
+            ..Default::default()
+        };
+        let env = environment
+            .get_environment("user".into(), "/home/user".into(), vec![])
+            .unwrap();
+        assert!(env.contains(&"A=plain".to_string()), "{:?}", env);
+        assert!(env.contains(&"D=s3cr3t".to_string()), "{:?}", env);
+
+        // Files are read on every call:
+        std::fs::write(&secret, "rotated").unwrap();
+        let env = environment
+            .get_environment("user".into(), "/home/user".into(), vec![])
+            .unwrap();
+        assert!(env.contains(&"D=rotated".to_string()), "{:?}", env);
+
+        std::fs::remove_file(&secret).unwrap();
+        let err = environment
+            .get_environment("user".into(), "/home/user".into(), vec![])
+            .unwrap_err();
+        assert!(format!("{:#}", err).contains("secret"), "{:#}", err);
+    }
+
     #[test]
     fn test_should_fail_on_not_existing_envvar() {
         let cfg = r#"command = "/bin/bash -c 'echo hello world'"
@@ -886,5 +2538,74 @@ working-directory = "/tmp/"
             Service::start_after("a", vec!["b"]),
         ];
         validate(services).expect("Validation failed");
+
+        // Service depends on itself:
+        let services = vec![Service::start_after("a", vec!["a"])];
+        let err = validate(services).unwrap_err().to_string();
+        assert!(err.contains("cannot start after itself"), "{}", err);
+
+        // Dependency cycle:
+        let services = vec![
+            Service::start_after("a", vec!["b"]),
+            Service::start_after("b", vec!["c"]),
+            Service::start_after("c", vec!["a"]),
+            Service::start_after("d", vec!["a"]),
+        ];
+        let err = validate(services).unwrap_err().to_string();
+        assert!(err.contains("a -> b -> c -> a"), "{}", err);
+        assert_eq!(err.matches("dependency cycle").count(), 1, "{}", err);
+
+        // Duplicated names, e.g. coming from different services paths:
+        let services = vec![
+            Service::from_name("a"),
+            Service::from_name("a"),
+            Service::from_name("a"),
+        ];
+        let err = validate(services).unwrap_err().to_string();
+        assert_eq!(err.matches("defined more than once").count(), 1, "{}", err);
+
+        // die-if-failed points to a missing service:
+        let mut service = Service::from_name("a");
+        service.termination.die_if_failed = vec!["b".into()];
+        let err = validate(vec![service]).unwrap_err().to_string();
+        assert!(err.contains("should die if 'b' fails"), "{}", err);
+
+        // Hooks with an empty command, or a failure policy not meant for them:
+        let mut service = Service::from_name("a");
+        service.hooks = toml::from_str(
+            r#"pre-start = { command = "true", on-failure = "fail-start" }
+post-start = { command = "" }
+post-stop = { command = "true", on-failure = "abort-stop" }"#,
+        )
+        .unwrap();
+        let err = validate(vec![service]).unwrap_err().to_string();
+        assert!(err.contains("post-start hook"), "{}", err);
+        assert!(err.contains("post-stop hook"), "{}", err);
+        assert!(!err.contains("pre-start"), "{}", err);
+
+        // Reload with both a signal and a command:
+        let mut service = Service::from_name("a");
+        service.reload = toml::from_str("signal = \"HUP\"\ncommand = \"true\"").unwrap();
+        let err = validate(vec![service]).unwrap_err().to_string();
+        assert!(err.contains("both a reload signal and"), "{}", err);
+
+        // Rlimit with the soft limit above the hard one:
+        let mut service = Service::from_name("a");
+        service.rlimits = toml::from_str(r#"as = { soft = "unlimited", hard = "1 GiB" }"#).unwrap();
+        let err = validate(vec![service]).unwrap_err().to_string();
+        assert!(err.contains("soft `as` rlimit"), "{}", err);
+
+        // Process attributes out of their range:
+        let mut service = Service::from_name("a");
+        service.process = toml::from_str("nice = -21\noom-score-adj = 1000").unwrap();
+        let err = validate(vec![service]).unwrap_err().to_string();
+        assert!(err.contains("`nice` of service 'a' is -21"), "{}", err);
+        assert!(!err.contains("oom-score-adj"), "{}", err);
+
+        // The old exit code of the services killed by SIGKILL:
+        let mut service = Service::from_name("a");
+        service.failure.successful_exit_code = vec![0, -137];
+        let err = validate(vec![service]).unwrap_err().to_string();
+        assert!(err.contains("successful-signals = [\"KILL\"]"), "{}", err);
     }
 }
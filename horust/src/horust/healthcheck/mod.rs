@@ -95,7 +95,7 @@ fn run(bus: BusConnector<Event>, services: Vec<Service>) {
                 let handle = Worker::new(service, bus.join_bus(), work_done_rcv).spawn_thread();
                 workers.insert(s_name, (worker_notifier, handle));
             }
-            Event::ServiceExited(s_name, _exit_code) => {
+            Event::ServiceExited(s_name, _reason) => {
                 if let Some((sender, handler)) = workers.remove(&s_name) {
                     stop_worker(sender, handler)
                 } else {
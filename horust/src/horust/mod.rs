@@ -11,12 +11,15 @@ use libc::{prctl, PR_SET_CHILD_SUBREAPER};
 pub use formats::Event;
 
 use crate::horust::bus::Bus;
-use crate::horust::formats::{validate, Service};
+use crate::horust::error::{ValidationError, ValidationErrors};
+use crate::horust::formats::{validate, ConfigFormat, Service};
 
+pub use self::config_check::{check_services, ConfigProblem};
 pub use self::formats::{get_sample_service, ExitStatus, HorustConfig};
 
 pub mod bus;
 mod commands_handler;
+mod config_check;
 mod error;
 pub mod formats;
 mod healthcheck;
@@ -45,20 +48,19 @@ impl Horust {
         Self::new(vec![Service::from_command(command)], uds_path)
     }
 
-    fn load_services_from_folders(paths: &[PathBuf]) -> Result<Vec<Service>> {
-        paths
-            .iter()
-            .map(|path| fetch_services(path))
-            .flat_map(|result| match result {
-                Ok(vec) => vec.into_iter().map(Ok).collect(),
-                Err(err) => vec![Err(err)],
-            })
-            .collect::<Result<Vec<_>>>()
-    }
     /// Create a new horust instance from multiple paths of services.
     pub fn from_services_dirs(paths: &[PathBuf], uds_path: PathBuf) -> Result<Self> {
-        let services = Self::load_services_from_folders(paths)?;
-        let services = validate(services)?;
+        Self::from_config(&HorustConfig::default(), paths, uds_path)
+    }
+
+    /// Create a new horust instance from the services defined in the config, together with the
+    /// ones loaded from multiple paths. The config's `defaults` are applied to all of them.
+    pub fn from_config(
+        config: &HorustConfig,
+        paths: &[PathBuf],
+        uds_path: PathBuf,
+    ) -> Result<Self> {
+        let services = load_services(config, paths)?;
         Ok(Horust::new(services, uds_path))
     }
 
@@ -101,9 +103,13 @@ impl Horust {
         commands_handler::spawn(
             self.join_bus(),
             self.uds_path.clone(),
-            self.services.iter().map(|s| s.name.clone()).collect(),
+            self.services.clone(),
+        );
+        let handle = supervisor::spawn(
+            self.join_bus(),
+            self.services.clone(),
+            self.uds_path.clone(),
         );
-        let handle = supervisor::spawn(self.join_bus(), self.services.clone());
         let bus = self
             .bus
             .take()
@@ -113,43 +119,80 @@ impl Horust {
     }
 }
 
-fn load_service<P>(path: P) -> Result<Service>
+/// Loads and validates the services defined in the config, together with the ones loaded from
+/// multiple paths. These are the services as Horust will run them: templated, with the `defaults`
+/// applied and their names set.
+pub fn load_services(config: &HorustConfig, paths: &[PathBuf]) -> Result<Vec<Service>> {
+    let mut services = config.services.clone();
+    for path in paths {
+        services.extend(fetch_services(path, &config.defaults)?);
+    }
+    Ok(validate(services)?)
+}
+
+/// Loads the service defined in path, merged with its drop-in fragments (`<path>.d/`).
+/// If it's a template service (e.g. `worker@.toml`), it will return a service for each one of its
+/// instances. Missing values are taken from `defaults`.
+fn load_service<P>(path: P, defaults: &toml::Table) -> Result<Vec<Service>>
 where
     P: AsRef<Path> + Sized + AsRef<OsStr> + Debug,
     PathBuf: From<P>,
 {
-    let res = Service::from_file(&path);
     let path = PathBuf::from(path);
-    res.map(|mut service| {
-        if service.name.is_empty() {
+    list_dropin_files(&path)
+        .and_then(|dropins| {
+            if Service::is_abstract_file(&path)? {
+                debug!("Skipping abstract service: {}", path.display());
+                return Ok(vec![]);
+            }
+            let files: Vec<PathBuf> = std::iter::once(path.clone()).chain(dropins).collect();
+            let mut service = Service::from_files(&files, None, defaults)?;
             let filename = path.file_name().unwrap().to_str().unwrap().to_owned();
-            service.name = filename;
-        }
-        service
-    })
-    .map_err(|error| {
-        let error = error.context(format!("Failed loading toml file: {:?}", path.display()));
-        error!("{:?}", error);
-        error
-    })
+            let template_name = path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .and_then(|stem| stem.strip_suffix('@'));
+            // A template file without `instances` fails like an empty one.
+            if service.instances.is_some() || template_name.is_some() {
+                let base_name = match template_name {
+                    _ if !service.name.is_empty() => service.name.clone(),
+                    Some(template_name) => template_name.to_owned(),
+                    None => filename,
+                };
+                return service.instantiate(&files, &base_name, defaults);
+            }
+            if service.name.is_empty() {
+                service.name = filename;
+            }
+            Ok(vec![service])
+        })
+        .map_err(|error| {
+            let error = error.context(format!("Failed loading service file: {:?}", path.display()));
+            error!("{:?}", error);
+            error
+        })
 }
 
-fn is_toml_file(path: &Path) -> bool {
-    let has_toml_extension = |path: &Path| {
-        path.extension()
-            .unwrap_or_else(|| "".as_ref())
-            .to_str()
-            .unwrap_or("")
-            .ends_with("toml")
-    };
-    path.is_file() && has_toml_extension(path)
+/// Lists the drop-in fragments of the service in path, sorted by name.
+/// Fragments are service files stored in the `<path>.d` directory, e.g. `myservice.toml.d/`.
+fn list_dropin_files(path: &Path) -> Result<Vec<PathBuf>> {
+    let mut dropins_dir = path.as_os_str().to_owned();
+    dropins_dir.push(".d");
+    let dropins_dir = PathBuf::from(dropins_dir);
+    if !dropins_dir.is_dir() {
+        return Ok(vec![]);
+    }
+    let mut dropins = list_service_files(&dropins_dir)?;
+    dropins.sort();
+    Ok(dropins)
 }
 
-// TODO: option to decide to not start if the deserialization of any service failed.
-/// Search for *.toml files in path, and deserialize them into Service.
-fn fetch_services(path: &Path) -> Result<Vec<Service>> {
-    debug!("Fetching services from: {}", path.display());
+fn is_service_file(path: &Path) -> bool {
+    path.is_file() && ConfigFormat::from_extension(path).is_some()
+}
 
+/// Lists the service files in path. If path is a file, it will be the only entry.
+fn list_service_files(path: &Path) -> Result<Vec<PathBuf>> {
     let paths = if path.is_file() {
         vec![path.to_path_buf()]
     } else {
@@ -163,12 +206,31 @@ fn fetch_services(path: &Path) -> Result<Vec<Service>> {
             .map(|direntry| direntry.path())
             .collect()
     };
-    let services = paths
-        .into_iter()
-        .filter(|p| is_toml_file(p))
-        .map(load_service)
-        .filter_map(Result::ok)
-        .collect::<Vec<Service>>();
+    Ok(paths.into_iter().filter(|p| is_service_file(p)).collect())
+}
+
+// TODO: option to decide to not start if the deserialization of any service failed.
+/// Search for service files (TOML, YAML or JSON) in path, and deserialize them into Service.
+/// Files which fail to load are skipped, unless they have validation errors (e.g. an inheritance
+/// cycle): like the ones found by `validate`, they are returned.
+fn fetch_services(path: &Path, defaults: &toml::Table) -> Result<Vec<Service>> {
+    debug!("Fetching services from: {}", path.display());
+
+    let mut services = vec![];
+    let mut errors = vec![];
+    for file in list_service_files(path)? {
+        match load_service(file, defaults) {
+            Ok(loaded) => services.extend(loaded),
+            Err(error) => {
+                if let Ok(error) = error.downcast::<ValidationError>() {
+                    errors.push(error);
+                }
+            }
+        }
+    }
+    if !errors.is_empty() {
+        return Err(ValidationErrors::new(errors).into());
+    }
     if services.is_empty() {
         error!("Horust: No (valid) services found in: {}", path.display());
     }
@@ -180,16 +242,23 @@ mod test {
     use std::fs;
     use std::io;
     use std::path::{Path, PathBuf};
+    use std::time::Duration;
 
     use crate::Horust;
     use tempfile::TempDir;
 
-    use crate::horust::fetch_services;
-    use crate::horust::formats::Service;
+    use crate::horust::error::{ValidationError, ValidationErrors};
+    use crate::horust::formats::{LogOutput, RestartStrategy, Service};
+    use crate::horust::load_service;
 
     const FIRST_SERVICE_FILENAME: &str = "my-first-service.toml";
     const SECOND_SERVICE_FILENAME: &str = "my-second-service.toml";
 
+    /// Fetches the services in path, without defaults.
+    fn fetch_services(path: &Path) -> anyhow::Result<Vec<Service>> {
+        super::fetch_services(path, &toml::Table::new())
+    }
+
     /// List files in path, filtering out directories
     fn list_files<P: AsRef<Path>>(path: P) -> io::Result<Vec<PathBuf>> {
         fs::read_dir(path)?
@@ -244,6 +313,217 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn test_fetch_template_services() -> io::Result<()> {
+        let tempdir = TempDir::with_prefix("horust").unwrap();
+        let template = r#"command = "worker --id ${INSTANCE}"
+instances = ["a", "b"]
+stdout = "/tmp/worker-${INSTANCE}.log"
+[environment]
+additional = { WORKER_ID = "${INSTANCE}" }
+"#;
+        fs::write(tempdir.path().join("worker@.toml"), template)?;
+        let mut services = fetch_services(tempdir.path()).unwrap();
+        services.sort_by(|a, b| a.name.cmp(&b.name));
+        assert_eq!(services.len(), 2);
+        let service = &services[1];
+        assert_eq!(service.name, "worker@b");
+        assert_eq!(service.instance(), Some("b"));
+        assert_eq!(service.command.to_string(), "worker --id b");
+        assert_eq!(service.stdout, "/tmp/worker-b.log".into());
+        assert_eq!(service.environment.additional["WORKER_ID"], "b");
+        assert!(service.instances.is_none());
+
+        // Instances as a count, with an explicit name:
+        let template = "name = \"job\"\ncommand = \"job ${INSTANCE}\"\ninstances = 3";
+        fs::write(tempdir.path().join("worker@.toml"), template)?;
+        let mut names: Vec<String> = fetch_services(tempdir.path())
+            .unwrap()
+            .into_iter()
+            .map(|s| s.name)
+            .collect();
+        names.sort();
+        assert_eq!(names, vec!["job@1", "job@2", "job@3"]);
+
+        // A template file needs some instances:
+        for instances in ["", "\ninstances = 0", "\ninstances = []"] {
+            let template = format!("command = \"job\"{}", instances);
+            fs::write(tempdir.path().join("worker@.toml"), template)?;
+            let error = fetch_services(tempdir.path()).unwrap_err();
+            let errors = error.downcast_ref::<ValidationErrors>().unwrap().errors();
+            assert!(matches!(
+                errors,
+                [ValidationError::NoInstances { service }] if service == "worker"
+            ));
+        }
+
+        // Only the services created from a template have an instance id.
+        fs::remove_file(tempdir.path().join("worker@.toml"))?;
+        fs::write(tempdir.path().join("user@host.toml"), r#"command = "job""#)?;
+        let services = fetch_services(tempdir.path()).unwrap();
+        assert_eq!(services[0].name, "user@host.toml");
+        assert_eq!(services[0].instance(), None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_fetch_services_with_dropins() -> io::Result<()> {
+        let tempdir = TempDir::with_prefix("horust").unwrap();
+        let base = r#"command = "app"
+start-after = ["a.toml"]
+[environment]
+additional = { A = "1", B = "2" }
+[termination]
+die-if-failed = ["a.toml"]
+"#;
+        fs::write(tempdir.path().join("app.toml"), base)?;
+        let dropins = tempdir.path().join("app.toml.d");
+        fs::create_dir(&dropins)?;
+        let second = r#"command = "app --verbose"
+start-after = ["c.toml"]
+[environment]
+additional = { B = "3" }
+"#;
+        let first = r#"start-after = ["b.toml"]
+[termination]
+die-if-failed = []
+"#;
+        fs::write(dropins.join("20-second.toml"), second)?;
+        fs::write(dropins.join("10-first.toml"), first)?;
+        fs::write(dropins.join("not-a-fragment"), "command = \"nope\"")?;
+
+        let services = fetch_services(tempdir.path()).unwrap();
+        assert_eq!(services.len(), 1);
+        let service = &services[0];
+        assert_eq!(service.name, "app.toml");
+        assert_eq!(service.command.to_string(), "app --verbose");
+        assert_eq!(service.start_after, vec!["a.toml", "b.toml", "c.toml"]);
+        assert!(service.termination.die_if_failed.is_empty());
+        assert_eq!(service.environment.additional["A"], "1");
+        assert_eq!(service.environment.additional["B"], "3");
+        Ok(())
+    }
+
+    #[test]
+    fn test_fetch_services_formats() -> io::Result<()> {
+        let tempdir = TempDir::with_prefix("horust").unwrap();
+        std::env::set_var("HORUST_TEST_FORMATS", "templated");
+        let yaml = r#"command: "app"
+stdout: "/tmp/${HORUST_TEST_FORMATS}.log"
+start-after: ["a.json"]
+termination:
+  wait: 3s
+"#;
+        let json =
+            r#"{"name": "json", "command": "app", "environment": {"additional": {"A": "1"}}}"#;
+        fs::write(tempdir.path().join("a.yml"), yaml)?;
+        fs::write(tempdir.path().join("a.json"), json)?;
+        fs::write(tempdir.path().join("a.txt"), "not a service")?;
+
+        let mut services = fetch_services(tempdir.path()).unwrap();
+        services.sort_by(|a, b| a.name.cmp(&b.name));
+        assert_eq!(services.len(), 2);
+        assert_eq!(services[0].name, "a.yml");
+        assert_eq!(services[0].stdout, "/tmp/templated.log".into());
+        assert_eq!(services[0].start_after, vec!["a.json"]);
+        assert_eq!(services[0].termination.wait, Duration::from_secs(3));
+        assert_eq!(services[1].name, "json");
+        assert_eq!(services[1].environment.additional["A"], "1");
+        Ok(())
+    }
+
+    #[test]
+    fn test_fetch_services_with_extends() -> io::Result<()> {
+        let tempdir = TempDir::with_prefix("horust").unwrap();
+        let common = r#"abstract = true
+[restart]
+attempts = 3
+[environment]
+additional = { A = "1", B = "2" }
+"#;
+        // `extends` is relative to the file's directory.
+        let base = r#"abstract = true
+extends = "bases/common.toml"
+command = "app"
+[environment]
+additional = { B = "3" }
+"#;
+        let bases = tempdir.path().join("bases");
+        fs::create_dir(&bases)?;
+        fs::write(bases.join("common.toml"), common)?;
+        fs::write(tempdir.path().join("base.toml"), base)?;
+        let app = r#"extends = "base.toml"
+[environment]
+additional = { C = "4" }
+"#;
+        fs::write(tempdir.path().join("app.toml"), app)?;
+
+        let services = fetch_services(tempdir.path()).unwrap();
+        assert_eq!(services.len(), 1);
+        let service = &services[0];
+        assert_eq!(service.name, "app.toml");
+        assert_eq!(service.command.to_string(), "app");
+        assert_eq!(service.restart.attempts, 3);
+        assert_eq!(service.environment.additional["A"], "1");
+        assert_eq!(service.environment.additional["B"], "3");
+        assert_eq!(service.environment.additional["C"], "4");
+
+        // Lists are replaced, not appended.
+        fs::write(bases.join("common.toml"), "start-after = [\"a\", \"b\"]")?;
+        let app = "extends = \"base.toml\"\nstart-after = [\"c\"]";
+        fs::write(tempdir.path().join("app.toml"), app)?;
+        let services = fetch_services(tempdir.path()).unwrap();
+        assert_eq!(services[0].start_after, vec!["c"]);
+
+        fs::write(tempdir.path().join("base.toml"), "extends = \"app.toml\"")?;
+        let error = load_service(tempdir.path().join("app.toml"), &toml::Table::new()).unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<ValidationError>(),
+            Some(ValidationError::InheritanceCycle { .. })
+        ));
+        // The inheritance cycle, found from both the files, doesn't let Horust start.
+        let error = fetch_services(tempdir.path()).unwrap_err();
+        let errors = error.downcast_ref::<ValidationErrors>().unwrap().errors();
+        assert_eq!(errors.len(), 2);
+        assert!(errors
+            .iter()
+            .all(|error| matches!(error, ValidationError::InheritanceCycle { .. })));
+        Ok(())
+    }
+
+    #[test]
+    fn test_fetch_services_with_defaults() -> io::Result<()> {
+        let tempdir = TempDir::with_prefix("horust").unwrap();
+        let defaults: toml::Table = toml::from_str(
+            r#"stdout = "/var/log/service.log"
+[restart]
+strategy = "always"
+[failure]
+successful-exit-code = [0, 1]
+"#,
+        )
+        .unwrap();
+        let a = r#"command = "a"
+[failure]
+successful-exit-code = [3]
+"#;
+        fs::write(tempdir.path().join("a.toml"), a)?;
+        fs::write(tempdir.path().join("b.toml"), r#"command = "b""#)?;
+
+        let mut services = super::fetch_services(tempdir.path(), &defaults).unwrap();
+        services.sort_by(|a, b| a.name.cmp(&b.name));
+        assert_eq!(services[0].failure.successful_exit_code, vec![3]);
+        assert_eq!(services[1].failure.successful_exit_code, vec![0, 1]);
+        for service in services {
+            assert_eq!(service.restart.strategy, RestartStrategy::Always);
+            assert_eq!(
+                service.stdout,
+                LogOutput::Path("/var/log/service.log".into())
+            );
+        }
+        Ok(())
+    }
+
     #[test]
     fn test_list_files() -> io::Result<()> {
         let tempdir = TempDir::with_prefix("horust").unwrap();
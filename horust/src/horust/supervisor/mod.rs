@@ -4,19 +4,23 @@
 
 use std::fmt::Debug;
 use std::ops::Mul;
+use std::path::PathBuf;
 use std::thread;
 use std::time::{Duration, Instant};
 
 use nix::sys::signal;
 use nix::unistd;
 
-pub(crate) use process_spawner::find_program;
+pub(crate) use process_spawner::{find_program, find_program_in};
 use repo::Repo;
 use service_handler::ServiceHandler;
 pub(crate) use signal_handling::init;
 
 use crate::horust::bus::BusConnector;
-use crate::horust::formats::{Event, ExitStatus, Service, ServiceStatus, ShuttingDown};
+use crate::horust::formats::{
+    cgroup_pids, Event, ExitStatus, HookFailure, HookKind, KillMode, Service, ServiceName,
+    ServiceStatus, ShuttingDown,
+};
 use crate::horust::healthcheck;
 
 mod process_spawner;
@@ -32,8 +36,12 @@ const MAX_PROCESS_REAPS_ITERS: u32 = 20;
 const INIT_PID: unistd::Pid = unistd::Pid::from_raw(1);
 
 // Spawns and runs this component in a new thread.
-pub fn spawn(bus: BusConnector<Event>, services: Vec<Service>) -> thread::JoinHandle<ExitStatus> {
-    thread::spawn(move || Supervisor::new(bus, services).run())
+pub fn spawn(
+    bus: BusConnector<Event>,
+    services: Vec<Service>,
+    uds_path: PathBuf,
+) -> thread::JoinHandle<ExitStatus> {
+    thread::spawn(move || Supervisor::new(bus, services, uds_path).run())
 }
 
 #[derive(Clone, Debug, Copy, PartialEq, Eq)]
@@ -47,32 +55,64 @@ pub struct Supervisor {
     /// The system is shutting down, no more services will be spawned.
     status: LifecycleStatus,
     repo: Repo,
+    /// Path of the commands socket, exported to the services.
+    uds_path: PathBuf,
 }
 
 impl Supervisor {
-    fn new(bus: BusConnector<Event>, services: Vec<Service>) -> Self {
+    fn new(bus: BusConnector<Event>, services: Vec<Service>, uds_path: PathBuf) -> Self {
         let repo = Repo::new(bus, services);
         Self {
             repo,
             status: LifecycleStatus::Running,
+            uds_path,
         }
     }
 
     /// Handle the events, returns Events (state changes) to be dispatched.
     fn handle_event(&mut self, ev: Event) -> Vec<Event> {
         match ev {
-            Event::ServiceExited(service_name, exit_code) => {
-                let pid = self.repo.get_sh(&service_name).pid.unwrap();
-                self.repo.remove_pid(pid);
+            Event::ServiceExited(service_name, reason) => {
+                let service_handler = self.repo.get_sh(&service_name);
+                match service_handler.pid {
+                    Some(pid) => self.repo.remove_pid(pid),
+                    // The exit of the group was already reported.
+                    None if service_handler.exit_reason.is_none() => return vec![],
+                    None => (),
+                }
                 let service_handler = self.repo.get_mut_sh(&service_name);
-                service_handler.shutting_down_start = None;
                 service_handler.pid = None;
+                service_handler.exit_reason = Some(reason);
+                if !service_handler.is_group_empty() {
+                    debug!(
+                        "{}: main process has exited, {}, stopping the rest of the group.",
+                        service_name, reason
+                    );
+                    if service_handler.is_in_killing() {
+                        return vec![];
+                    }
+                    service_handler.status = ServiceStatus::InKilling;
+                    service_handler.shutting_down_started();
+                    send_termination_step(service_handler, 0);
+                    return vec![Event::StatusChanged(service_name, ServiceStatus::InKilling)];
+                }
+                if service_handler.running_hook.is_some() {
+                    // The exit is reported again once the hook has finished.
+                    return vec![];
+                }
+                if service_handler.finished_hook != Some(HookKind::PostStop)
+                    && self.run_hook(&service_name, HookKind::PostStop)
+                {
+                    return vec![];
+                }
+                let service_handler = self.repo.get_mut_sh(&service_name);
+                service_handler.shutting_down_start = None;
+                service_handler.pgid = None;
+                service_handler.exit_reason = None;
+                service_handler.finished_hook = None;
 
-                let has_failed = !service_handler
-                    .service()
-                    .failure
-                    .successful_exit_code
-                    .contains(&exit_code);
+                let has_failed = !service_handler.service().failure.is_successful(reason)
+                    || service_handler.start_failed;
 
                 // If it has failed too quickly, increase service_handler's restart attempts
                 // and check if it has more attempts left.
@@ -86,18 +126,18 @@ impl Supervisor {
                         && service_handler.has_some_failed_healthchecks())
                 {
                     warn!(
-                        "Service: {} has failed, exit code: {}, healthchecks: {} ({:?})",
+                        "Service: {} has failed, {}, healthchecks: {} ({:?})",
                         service_handler.name(),
-                        exit_code,
+                        reason,
                         service_handler.has_some_failed_healthchecks(),
                         service_handler.healthiness_checks_failed
                     );
                     ServiceStatus::Failed
                 } else {
                     info!(
-                        "Service: {} successfully exited with: {}.",
+                        "Service: {} successfully terminated, {}.",
                         service_handler.name(),
-                        exit_code
+                        reason
                     );
                     ServiceStatus::Success
                 };
@@ -112,6 +152,8 @@ impl Supervisor {
             Event::Run(service_name) if self.repo.get_sh(&service_name).is_initial() => {
                 let service_handler = self.repo.get_mut_sh(&service_name);
                 service_handler.status = ServiceStatus::Starting;
+                service_handler.finished_hook = None;
+                service_handler.start_failed = false;
                 let evs = vec![Event::StatusChanged(service_name, ServiceStatus::Starting)];
 
                 let res = healthcheck::prepare_service(&service_handler.service().healthiness);
@@ -139,21 +181,22 @@ impl Supervisor {
                 process_spawner::spawn_fork_exec_handler(
                     service_handler.service().clone(),
                     backoff,
+                    service_handler.restart_attempts,
+                    self.uds_path.clone(),
                     self.repo.bus.join_bus(),
                 );
                 evs
             }
-            Event::SpawnFailed(s_name) => {
+            Event::SpawnFailed(s_name, _) => {
                 let service_handler = self.repo.get_mut_sh(&s_name);
                 service_handler.status = ServiceStatus::Failed;
                 vec![Event::StatusUpdate(s_name, ServiceStatus::Failed)]
             }
             Event::Kill(service_name) => {
                 debug!("Received kill request");
-                let service_handler = self.repo.get_mut_sh(&service_name);
+                let service_handler = self.repo.get_sh(&service_name);
                 if service_handler.is_in_killing() {
-                    service_handler.shutting_down_started();
-                    kill(service_handler, None);
+                    self.stop(&service_name);
                 } else {
                     debug!(
                         "Cannot send kill request, service was in: {}",
@@ -162,10 +205,116 @@ impl Supervisor {
                 }
                 vec![]
             }
+            Event::HookFinished(service_name, kind, result) => {
+                match &result {
+                    Ok(()) => info!("{}: {} hook succeeded.", service_name, kind),
+                    Err(error) => warn!("{}: {} hook failed: {}", service_name, kind, error),
+                }
+                if kind == HookKind::PreStart {
+                    // It's run by the spawner, which handles its failure.
+                    return vec![];
+                }
+                let is_running = self.status == LifecycleStatus::Running;
+                let service_handler = self.repo.get_mut_sh(&service_name);
+                service_handler.running_hook = None;
+                service_handler.finished_hook = Some(kind);
+                let hook = service_handler.service().hooks.get(kind);
+                let on_failure = hook.map(|hook| hook.on_failure);
+                if result.is_err() && service_handler.exit_reason.is_none() {
+                    match on_failure {
+                        Some(HookFailure::FailStart) if service_handler.is_running() => {
+                            service_handler.start_failed = true;
+                            return vec![
+                                Event::new_status_update(&service_name, ServiceStatus::InKilling),
+                                Event::Kill(service_name),
+                            ];
+                        }
+                        Some(HookFailure::AbortStop)
+                            if service_handler.is_in_killing()
+                                && service_handler.shutting_down_start.is_none()
+                                && is_running =>
+                        {
+                            warn!("{}: the stop is aborted.", service_name);
+                            service_handler.status = ServiceStatus::Running;
+                            service_handler.finished_hook = None;
+                            return vec![Event::new_status_changed(
+                                &service_name,
+                                ServiceStatus::Running,
+                            )];
+                        }
+                        _ => (),
+                    }
+                }
+                // A stop might have been waiting for the hook.
+                if service_handler.is_in_killing()
+                    && service_handler.exit_reason.is_none()
+                    && service_handler.shutting_down_start.is_none()
+                {
+                    self.stop(&service_name);
+                }
+                vec![]
+            }
+            Event::Reload(service_name) => {
+                let bus = self.repo.bus.join_bus();
+                let service_handler = self.repo.get_sh(&service_name);
+                if !service_handler.is_running() {
+                    let error =
+                        format!("Cannot reload, service was in: {}", service_handler.status);
+                    return vec![Event::ReloadFinished(service_name, Err(error))];
+                }
+                // The service is neither stopped nor restarted: its status is left untouched.
+                let reload = &service_handler.service().reload;
+                match (service_handler.pid(), reload.signal, &reload.command) {
+                    (Some(pid), Some(signal), _) => {
+                        let signal: signal::Signal = signal.into();
+                        info!("{}: reloading, sending {}.", service_name, signal);
+                        let result = signal::kill(pid, signal)
+                            .map_err(|error| format!("Failed to send {}: {}", signal, error));
+                        return vec![Event::ReloadFinished(service_name, result)];
+                    }
+                    (Some(_), None, Some(_)) => {
+                        info!("{}: reloading, running the reload command.", service_name);
+                        process_spawner::spawn_reload_handler(
+                            service_handler.service().clone(),
+                            service_handler.restart_attempts,
+                            self.uds_path.clone(),
+                            bus,
+                        );
+                    }
+                    (None, _, _) => {
+                        let error = "Cannot reload, service has no pid".to_string();
+                        return vec![Event::ReloadFinished(service_name, Err(error))];
+                    }
+                    (Some(_), None, None) => {
+                        let error = "Nothing to reload, no signal nor command is set".to_string();
+                        return vec![Event::ReloadFinished(service_name, Err(error))];
+                    }
+                }
+                vec![]
+            }
+            Event::ReloadFinished(service_name, result) => {
+                match result {
+                    Ok(()) => info!("{}: reload succeeded.", service_name),
+                    Err(error) => warn!("{}: reload failed: {}", service_name, error),
+                }
+                vec![]
+            }
+            Event::TerminationStep(service_name, step) => {
+                let service_handler = self.repo.get_mut_sh(&service_name);
+                // Only move forward by one step, duplicated requests are ignored.
+                if service_handler.is_in_killing() && step == service_handler.termination_step + 1 {
+                    send_termination_step(service_handler, step);
+                }
+                vec![]
+            }
             Event::ForceKill(service_name) if self.repo.get_sh(&service_name).is_in_killing() => {
                 debug!("Going to forcekill {}", service_name);
                 let service_handler = self.repo.get_mut_sh(&service_name);
-                kill(service_handler, Some(signal::SIGKILL));
+                kill(service_handler, signal::SIGKILL);
+                if service_handler.exit_reason.is_some() {
+                    // The main process is gone already, the exit is reported with the group's.
+                    return vec![];
+                }
                 service_handler.status = ServiceStatus::Failed;
                 vec![Event::new_status_changed(
                     service_handler.name(),
@@ -177,10 +326,11 @@ impl Supervisor {
 
                 let service_handler = self.repo.get_mut_sh(&service_name);
                 service_handler.pid = Some(pid);
+                service_handler.pgid = Some(pid);
                 if service_handler.is_in_killing() {
                     // Ah! Gotcha!
                     service_handler.shutting_down_start = Some(Instant::now());
-                    kill(service_handler, None)
+                    send_termination_step(service_handler, 0);
                 } else {
                     service_handler.status = ServiceStatus::Started;
                     return vec![Event::StatusChanged(service_name, ServiceStatus::Started)];
@@ -214,6 +364,9 @@ impl Supervisor {
                 let (new_sh, new_status) = service_handler.change_status(new_status);
                 if new_status != service_handler.status {
                     self.repo.insert_sh_by_name(service_name.clone(), new_sh);
+                    if new_status == ServiceStatus::Running {
+                        self.run_hook(&service_name, HookKind::PostStart);
+                    }
                     // this is the only place where the new_status changed is emitted.
                     vec![Event::new_status_changed(&service_name, new_status)]
                 } else {
@@ -231,6 +384,44 @@ impl Supervisor {
         }
     }
 
+    /// Runs the `kind` hook of the service, if it's defined. Returns true if it was started.
+    fn run_hook(&mut self, service_name: &ServiceName, kind: HookKind) -> bool {
+        let service = self.repo.get_sh(service_name).service();
+        if service.hooks.get(kind).is_none() {
+            return false;
+        }
+        let bus = self.repo.bus.join_bus();
+        let service_handler = self.repo.get_mut_sh(service_name);
+        debug!("{}: running the {} hook.", service_name, kind);
+        service_handler.running_hook = Some(kind);
+        process_spawner::spawn_hook_handler(
+            service_handler.service().clone(),
+            kind,
+            service_handler.restart_attempts,
+            self.uds_path.clone(),
+            bus,
+        );
+        true
+    }
+
+    /// Stops the service: its pre-stop hook is run first, then the termination sequence starts.
+    fn stop(&mut self, service_name: &ServiceName) {
+        let service_handler = self.repo.get_sh(service_name);
+        if service_handler.running_hook.is_some() {
+            debug!("{}: the stop will continue after the hook.", service_name);
+            return;
+        }
+        if service_handler.pid.is_some()
+            && service_handler.finished_hook != Some(HookKind::PreStop)
+            && self.run_hook(service_name, HookKind::PreStop)
+        {
+            return;
+        }
+        let service_handler = self.repo.get_mut_sh(service_name);
+        service_handler.shutting_down_started();
+        send_termination_step(service_handler, 0);
+    }
+
     /// Blocking call.
     /// This function will run the services and reap dead pids.
     fn run(mut self) -> ExitStatus {
@@ -297,30 +488,59 @@ impl Supervisor {
     }
 }
 
-/// A Kill wrapper which will send a signal to sh.
-/// It will send the signal set out in the termination section of the service
-fn kill(sh: &ServiceHandler, signal: Option<signal::Signal>) {
-    let signal = signal.unwrap_or_else(|| sh.service().termination.signal.into());
+/// Sends the signal of the `step` of the termination sequence of sh.
+fn send_termination_step(sh: &mut ServiceHandler, step: usize) {
+    let steps = sh.service().termination.steps();
+    let signal = steps[step].signal.into();
+    info!(
+        "{}: termination step {}/{}, sending {} and waiting {:?}.",
+        sh.name(),
+        step + 1,
+        steps.len(),
+        signal,
+        steps[step].wait
+    );
+    sh.termination_step = step;
+    kill(sh, signal);
+}
+
+/// A Kill wrapper which will send a signal to sh, to the processes selected by its kill mode.
+fn kill(sh: &ServiceHandler, signal: signal::Signal) {
     debug!("Going to send {} signal to pid {:?}", signal, sh.pid());
-    if let Some(pid) = sh.pid() {
-        if let Err(error) = signal::kill(pid, signal) {
-            match error {
-                // No process or process group can be found corresponding to that specified by pid
-                // It has exited already, so it's fine.
-                nix::errno::Errno::ESRCH => (),
-                _ => error!(
-                    "Error killing the process: {}, service: {}, pid: {:?}",
-                    error,
-                    sh.name(),
-                    pid,
-                ),
-            }
+    let result = match (sh.service().termination.kill_mode, sh.pid(), sh.pgid) {
+        (KillMode::Process, Some(pid), _) => signal::kill(pid, signal),
+        (KillMode::ProcessGroup, _, Some(pgid)) => signal::killpg(pgid, signal),
+        (KillMode::Cgroup, pid, _) => {
+            let mut pids = cgroup_pids(sh.name()).unwrap_or_else(|error| {
+                debug!("{}: {:?}", sh.name(), error);
+                vec![]
+            });
+            // The main process is signalled even if it couldn't be moved into the cgroup.
+            pids.extend(pid.filter(|pid| !pids.contains(pid)));
+            pids.into_iter()
+                .try_for_each(|pid| match signal::kill(pid, signal) {
+                    Err(nix::errno::Errno::ESRCH) => Ok(()),
+                    result => result,
+                })
+        }
+        _ => {
+            warn!(
+                "{}: Missing pid to kill but service was in {:?} state.",
+                sh.name(),
+                sh.status
+            );
+            Ok(())
         }
-    } else {
-        warn!(
-            "{}: Missing pid to kill but service was in {:?} state.",
+    };
+    match result {
+        // No process or process group can be found corresponding to that specified by pid
+        // It has exited already, so it's fine.
+        Ok(()) | Err(nix::errno::Errno::ESRCH) => (),
+        Err(error) => error!(
+            "Error killing the process: {}, service: {}, pid: {:?}",
+            error,
             sh.name(),
-            sh.status
-        );
+            sh.pid(),
+        ),
     }
 }
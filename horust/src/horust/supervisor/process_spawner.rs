@@ -1,29 +1,42 @@
-use anyhow::{anyhow, Context, Result};
-use crossbeam::channel::{after, tick};
+use anyhow::{anyhow, bail, Result};
+use crossbeam::channel::{after, bounded, tick};
 use nix::errno::Errno;
 use nix::fcntl;
+use nix::mount::{mount, MsFlags};
+use nix::sched::{sched_setaffinity, CloneFlags, CpuSet};
+use nix::sys::resource;
+use nix::sys::signal::{self, Signal};
+use nix::sys::stat::{self, Mode};
+use nix::sys::wait::{waitpid, WaitStatus};
 use nix::unistd;
-use nix::unistd::{fork, ForkResult, Pid, Uid};
-use std::ffi::{CStr, CString};
-use std::os::unix::io::AsRawFd;
+use nix::unistd::{fork, ForkResult, Gid, Pid, Uid};
+use std::collections::HashMap;
+use std::ffi::{CStr, CString, OsStr};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
 use std::path::{Path, PathBuf};
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use std::{fs::File, io::BufReader};
 use std::{fs::OpenOptions, ops::Add};
 use std::{
-    io::{self, Read},
+    io::{self, Read, Write},
     os::fd::OwnedFd,
 };
 use std::fs;
 
 use crate::horust::bus::BusConnector;
-use crate::horust::formats::{Event, LogOutput, Service};
+use crate::horust::formats::{
+    Command, Event, ExitReason, Group, HookFailure, HookKind, LogOutput, ProcessAttributes,
+    Rlimits, Sandbox, Service,
+};
 use crate::horust::signal_safe::panic_ssafe;
 
 /// Run another thread that will wait for the start delay and handle the fork / exec
 pub(crate) fn spawn_fork_exec_handler(
     service: Service,
     backoff: Duration,
+    restart_count: u32,
+    socket_path: PathBuf,
     bus: BusConnector<Event>,
 ) {
     std::thread::spawn(move || {
@@ -41,17 +54,18 @@ pub(crate) fn spawn_fork_exec_handler(
                     recv(ticker) -> _ => {
                         let is_shutting_down = bus.try_get_events().into_iter().any(is_shutting_down_ev);
                         if is_shutting_down {
-                            break Event::SpawnFailed(service.name.clone());
+                            break Event::SpawnFailed(service.name.clone(), "Horust is shutting down.".into());
                         }
                     },
-                    recv(timeout) -> _ => break match spawn_process(&service) {
+                    recv(timeout) -> _ => break match run_pre_start_hook(&service, restart_count, &socket_path, &bus)
+                        .and_then(|_| spawn_process(&service, restart_count, &socket_path)) {
                             Ok(pid) => {
                                 debug!("Setting pid:{} for service: {}", pid, service.name);
                                 Event::new_pid_changed(service.name.clone(), pid)
                             }
                             Err(error) => {
                                 error!("Failed spawning the process: {}", error);
-                                Event::SpawnFailed(service.name)
+                                Event::SpawnFailed(service.name, format!("{:#}", error))
                             }
                     },
             }
@@ -60,11 +74,98 @@ pub(crate) fn spawn_fork_exec_handler(
     });
 }
 
-/// Produces the execvpe arguments out of a `Service`
+/// Runs the pre-start hook of the service, if any. It fails only if the start should fail.
+fn run_pre_start_hook(
+    service: &Service,
+    restart_count: u32,
+    socket_path: &Path,
+    bus: &BusConnector<Event>,
+) -> Result<()> {
+    let Some(hook) = &service.hooks.pre_start else {
+        return Ok(());
+    };
+    let result = run_hook(
+        service,
+        &hook.command,
+        hook.timeout,
+        restart_count,
+        socket_path,
+    )
+    .map_err(|e| format!("{:#}", e));
+    bus.send_event(Event::HookFinished(
+        service.name.clone(),
+        HookKind::PreStart,
+        result.clone(),
+    ));
+    match result {
+        Err(error) if hook.on_failure == HookFailure::FailStart => {
+            Err(anyhow!("The pre-start hook has failed: {}", error))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Run another thread that will run a hook of the service, and report its result on the bus.
+pub(crate) fn spawn_hook_handler(
+    service: Service,
+    kind: HookKind,
+    restart_count: u32,
+    socket_path: PathBuf,
+    bus: BusConnector<Event>,
+) {
+    std::thread::spawn(move || {
+        let hook = service.hooks.get(kind).expect("Hook is defined");
+        let result = run_hook(
+            &service,
+            &hook.command,
+            hook.timeout,
+            restart_count,
+            &socket_path,
+        )
+        .map_err(|error| format!("{:#}", error));
+        bus.send_event(Event::HookFinished(service.name.clone(), kind, result));
+    });
+}
+
+/// Run another thread that will run the reload command of the service, and report its result on
+/// the bus.
+pub(crate) fn spawn_reload_handler(
+    service: Service,
+    restart_count: u32,
+    socket_path: PathBuf,
+    bus: BusConnector<Event>,
+) {
+    std::thread::spawn(move || {
+        let reload = &service.reload;
+        let command = reload.command.as_ref().expect("Reload command is defined");
+        let result = run_hook(
+            &service,
+            command,
+            reload.timeout,
+            restart_count,
+            &socket_path,
+        )
+        .map_err(|error| format!("{:#}", error));
+        bus.send_event(Event::ReloadFinished(service.name.clone(), result));
+    });
+}
+
+/// Produces the execvpe arguments for running `command` as the `Service`
 #[inline]
-fn exec_args(service: &Service) -> Result<(CString, Vec<CString>, Vec<CString>)> {
-    let chunks: Vec<String> =
-        shlex::split(&service.command).context(format!("Invalid command: {}", service.command,))?;
+fn exec_args(
+    service: &Service,
+    command: &Command,
+    shell: bool,
+    restart_count: u32,
+    socket_path: &Path,
+) -> Result<(CString, Vec<CString>, Vec<CString>)> {
+    let environment = service.get_environment(restart_count, socket_path)?;
+    let chunks = command.args(shell)?;
+    // With `shell`, variables are expanded by the shell itself, and an argv list is run as is.
+    let chunks = match command {
+        Command::Line(_) if !shell => expand_args(chunks, &environment),
+        _ => chunks,
+    };
     let program_name = String::from(chunks.first().unwrap());
     let to_cstring = |s: Vec<String>| {
         s.into_iter()
@@ -72,79 +173,262 @@ fn exec_args(service: &Service) -> Result<(CString, Vec<CString>, Vec<CString>)>
             .collect::<Result<Vec<_>>>()
     };
     let arg_cstrings = to_cstring(chunks)?;
-    let environment = service.get_environment()?;
     let env_cstrings = to_cstring(environment)?;
     let path = if program_name.contains('/') {
         program_name.to_string()
     } else {
-        find_program(&program_name)?
+        // The program is looked up in the PATH of the service, which might differ from ours.
+        let path_var = env_cstrings
+            .iter()
+            .find_map(|var| var.to_bytes().strip_prefix(b"PATH="))
+            .ok_or_else(|| anyhow!("PATH environment variable is not set"))?;
+        find_program_in(&program_name, OsStr::from_bytes(path_var))?
     };
     Ok((CString::new(path)?, arg_cstrings, env_cstrings))
 }
 
+/// Expands `$VAR` and `${VAR}` in every argument using the environment of the service, so each
+/// argument stays a single one even if the value contains spaces.
+/// Variables missing from the service's environment are looked up in Horust's one, as commands
+/// used to be templated with it when loading the service.
+/// `$$` is an escaped `$`, and unknown variables are left untouched.
+fn expand_args(args: Vec<String>, environment: &[String]) -> Vec<String> {
+    let environment: HashMap<&str, &str> = environment
+        .iter()
+        .filter_map(|var| var.split_once('='))
+        .collect();
+    let lookup = |var: &str| {
+        environment
+            .get(var)
+            .map(|value| value.to_string())
+            .or_else(|| std::env::var(var).ok())
+    };
+    args.into_iter()
+        .map(|arg| shellexpand::env_with_context_no_errors(&arg, lookup).into_owned())
+        .collect()
+}
+
+/// The steps done in the child process before running the service. If one of them fails, the
+/// child reports it to the parent through the error pipe.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+enum ChildStep {
+    RedirectStdout,
+    RedirectStderr,
+    Sandbox,
+    Chdir,
+    Setsid,
+    Setrlimit,
+    Nice,
+    Ionice,
+    CpuAffinity,
+    OomScoreAdj,
+    Setgroups,
+    Setgid,
+    Setuid,
+    Exec,
+}
+
+impl ChildStep {
+    const ALL: [ChildStep; 14] = [
+        ChildStep::RedirectStdout,
+        ChildStep::RedirectStderr,
+        ChildStep::Sandbox,
+        ChildStep::Chdir,
+        ChildStep::Setsid,
+        ChildStep::Setrlimit,
+        ChildStep::Nice,
+        ChildStep::Ionice,
+        ChildStep::CpuAffinity,
+        ChildStep::OomScoreAdj,
+        ChildStep::Setgroups,
+        ChildStep::Setgid,
+        ChildStep::Setuid,
+        ChildStep::Exec,
+    ];
+
+    fn description(self) -> &'static str {
+        match self {
+            ChildStep::RedirectStdout => "Failed to redirect stdout",
+            ChildStep::RedirectStderr => "Failed to redirect stderr",
+            ChildStep::Sandbox => "Failed to set up the sandbox",
+            ChildStep::Chdir => "Failed to change the working directory",
+            ChildStep::Setsid => "Failed to create a new session",
+            ChildStep::Setrlimit => "Failed to set the resource limits",
+            ChildStep::Nice => "Failed to set the nice value",
+            ChildStep::Ionice => "Failed to set the I/O scheduling class and priority",
+            ChildStep::CpuAffinity => "Failed to set the CPU affinity",
+            ChildStep::OomScoreAdj => "Failed to set the OOM score adjustment",
+            ChildStep::Setgroups => "Failed to set the supplementary groups",
+            ChildStep::Setgid => "Failed to set the group",
+            ChildStep::Setuid => "Failed to set the user",
+            ChildStep::Exec => "Failed to exec the new process",
+        }
+    }
+
+    /// Exit code of the child if this step fails. The parent reports the error from the pipe,
+    /// these are used only if it couldn't be written.
+    fn exit_code(self) -> i32 {
+        match self {
+            ChildStep::RedirectStdout => 101,
+            ChildStep::RedirectStderr => 102,
+            _ => 103,
+        }
+    }
+}
+
+/// Size of the message sent on the error pipe: the step, and the errno.
+const CHILD_ERROR_LEN: usize = 5;
+
+/// Decodes the message sent by the child on the error pipe.
+fn decode_child_error(message: &[u8]) -> Option<(ChildStep, Errno)> {
+    if message.len() != CHILD_ERROR_LEN {
+        return None;
+    }
+    let step = *ChildStep::ALL.get(message[0] as usize)?;
+    let errno = i32::from_le_bytes(message[1..].try_into().ok()?);
+    Some((step, Errno::from_raw(errno)))
+}
+
+/// The user and groups the child switches to before running the service.
+struct Credentials {
+    uid: Uid,
+    /// If unset, the group of Horust is kept.
+    gid: Option<Gid>,
+    /// If unset, the supplementary groups of Horust are kept.
+    groups: Option<Vec<Gid>>,
+}
+
+impl Credentials {
+    /// The configured groups are always set. Otherwise, the primary and supplementary groups of
+    /// the user are set like `initgroups` does, but only if Horust runs as root.
+    /// They are resolved here, as reading /etc/group is not async-signal-safe.
+    fn new(service: &Service) -> Result<Self> {
+        let uid = service.user.get_uid()?;
+        let is_root = unistd::geteuid().is_root();
+        let user = if is_root {
+            unistd::User::from_uid(uid)?
+        } else {
+            None
+        };
+        let gid = match (&service.group, &user) {
+            (Some(group), _) => Some(group.get_gid()?),
+            (None, Some(user)) => Some(user.gid),
+            // Otherwise the service would keep the group of Horust, e.g. root.
+            (None, None) if is_root && uid != unistd::geteuid() => bail!(
+                "User `{}` not found in /etc/passwd, its group must be set with `group`.",
+                uid
+            ),
+            (None, None) => None,
+        };
+        let groups = match (&service.supplementary_groups, &user) {
+            (Some(groups), _) => Some(groups.iter().map(Group::get_gid).collect::<Result<_>>()?),
+            (None, Some(user)) => {
+                let name = CString::new(user.name.as_str())?;
+                Some(unistd::getgrouplist(&name, gid.unwrap_or(user.gid))?)
+            }
+            // A user missing from /etc/passwd is not a member of any group.
+            (None, None) if is_root => Some(vec![]),
+            (None, None) => None,
+        };
+        Ok(Self { uid, gid, groups })
+    }
+}
+
 #[inline]
 fn child_process_main(
     service: &Service,
     path: CString,
     cwd: PathBuf,
-    uid: Uid,
+    credentials: &Credentials,
     arg_cptr: Vec<&CStr>,
     env_cptr: Vec<&CStr>,
+    error_pipe: &OwnedFd,
 ) {
-    if let Err(errno) = redirect_output(&service.stdout, LogOutput::Stdout) {
-        panic_ssafe(
-            "child_process_main: Redirect stdout failed.",
-            Some(&service.name),
-            errno,
-            101,
-        );
-    }
-    if let Err(errno) = redirect_output(&service.stderr, LogOutput::Stderr) {
-        panic_ssafe(
-            "child_process_main: Redirect stderr failed.",
-            Some(&service.name),
-            errno,
-            102,
-        );
-    }
-    if let Err(errno) = exec(path, arg_cptr, env_cptr, uid, cwd) {
+    let result = redirect_output(&service.stdout, LogOutput::Stdout)
+        .map_err(|errno| (ChildStep::RedirectStdout, errno))
+        .and_then(|_| {
+            redirect_output(&service.stderr, LogOutput::Stderr)
+                .map_err(|errno| (ChildStep::RedirectStderr, errno))
+        })
+        .and_then(|_| setup_sandbox(&service.sandbox).map_err(|errno| (ChildStep::Sandbox, errno)))
+        .and_then(|_| {
+            exec(
+                path,
+                arg_cptr,
+                env_cptr,
+                &service.rlimits,
+                &service.process,
+                credentials,
+                cwd,
+            )
+        });
+    if let Err((step, errno)) = result {
+        let mut message = [step as u8; CHILD_ERROR_LEN];
+        message[1..].copy_from_slice(&(errno as i32).to_le_bytes());
+        // If the parent can't be told, it will only see the exit code.
+        let _ = unistd::write(error_pipe, &message);
         panic_ssafe(
-            "child_process_main: Failed to exec the new process.",
+            step.description(),
             Some(&service.name),
             errno,
-            103,
+            step.exit_code(),
         );
     }
 }
 
 /// Fork the process
-fn spawn_process(service: &Service) -> Result<Pid> {
+fn spawn_process(service: &Service, restart_count: u32, socket_path: &Path) -> Result<Pid> {
     debug!("Spawning process for service: {}", service.name);
-    let (path, arg_cstrings, env_cstrings) = exec_args(service)?;
-    let uid = service.user.get_uid()?;
+    let (path, arg_cstrings, env_cstrings) = exec_args(
+        service,
+        &service.command,
+        service.shell,
+        restart_count,
+        socket_path,
+    )?;
+    let credentials = Credentials::new(service)?;
     let cwd = service.working_directory.clone();
     let arg_cptr: Vec<&CStr> = arg_cstrings.iter().map(|c| c.as_c_str()).collect();
     let env_cptr: Vec<&CStr> = env_cstrings.iter().map(|c| c.as_c_str()).collect();
     let mut service_copy = service.clone();
+    // Closed on a successful exec, otherwise the child writes the failed step into it.
+    let (error_read, error_write) = unistd::pipe2(fcntl::OFlag::O_CLOEXEC)?;
     let (pipe_read, pipe_write) = if service.stdout_rotate_size > 0 {
         let (pipe_read, pipe_write) = unistd::pipe()?;
         (Some(pipe_read), Some(pipe_write))
     } else {
         (None, None)
     };
-    match unsafe { fork() } {
+    let namespaces = service.sandbox.clone_flags();
+    let fork_result = if namespaces.is_empty() {
+        unsafe { fork() }
+    } else {
+        unsafe { fork_in_namespaces(namespaces) }
+    };
+    match fork_result {
         Ok(ForkResult::Child) => {
             if let Some(pipe_write) = &pipe_write {
                 drop(pipe_read.unwrap());
                 service_copy.stdout = LogOutput::Pipe(pipe_write.as_raw_fd());
             }
-            child_process_main(&service_copy, path, cwd, uid, arg_cptr, env_cptr);
+            drop(error_read);
+            child_process_main(
+                &service_copy,
+                path,
+                cwd,
+                &credentials,
+                arg_cptr,
+                env_cptr,
+                &error_write,
+            );
             unreachable!();
             // Here the "pipe_write" would go out of scope and its descriptor would be closed.
             // But because child_process_main() does an exec() and never returns, the raw
             // descriptor inside the LogOutput::Pipe stays open.
         }
         Ok(ForkResult::Parent { child, .. }) => {
+            drop(error_write);
             pipe_read.and_then(|pipe| {
                 drop(pipe_write.unwrap());
                 std::thread::spawn(move || {
@@ -153,19 +437,195 @@ fn spawn_process(service: &Service) -> Result<Pid> {
                 None::<()>
             });
             // only the root user and authorized users can manage the cgroup
-            if let Err(err) = service.resource_limit.apply(&service.name, child) {
+            if let Err(err) =
+                service
+                    .resource_limit
+                    .apply(&service.name, child, service.termination.kill_mode)
+            {
                 warn!(
                     "Failed to add the resource limit to {}: {}",
                     &service.name, err
                 );
             }
+            // Wait for the exec, or for the child to report what went wrong. The failed child
+            // is then reaped like any other process.
+            let mut message = vec![];
+            File::from(error_read).read_to_end(&mut message)?;
+            if !message.is_empty() {
+                return Err(match decode_child_error(&message) {
+                    Some((step, errno)) => anyhow!("{}: {}", step.description(), errno),
+                    None => anyhow!("Invalid message from the child process: {:?}", message),
+                });
+            }
             debug!("Spawned child with PID {}.", child);
             Ok(child)
         }
+        Err(err) if !namespaces.is_empty() => {
+            Err(anyhow!("Failed to create the namespaces: {}", err))
+        }
+        Err(err) => Err(Into::into(err)),
+    }
+}
+
+/// Size of the exit status sent by the intermediate process of a hook: its kind, and the value.
+const HOOK_STATUS_LEN: usize = 5;
+
+/// Runs a hook command of the service and waits for it to exit, killing it after `timeout`.
+///
+/// The reaper collects every child of Horust, so the hook is spawned by an intermediate process
+/// which waits for it, and reports its pid and exit status through a pipe.
+fn run_hook(
+    service: &Service,
+    command: &Command,
+    timeout: Duration,
+    restart_count: u32,
+    socket_path: &Path,
+) -> Result<()> {
+    debug!("Running hook for service: {}", service.name);
+    // Hooks are not sandboxed: they run in the namespaces of Horust.
+    let service = &Service {
+        sandbox: Default::default(),
+        ..service.clone()
+    };
+    let (path, arg_cstrings, env_cstrings) =
+        exec_args(service, command, false, restart_count, socket_path)?;
+    let credentials = Credentials::new(service)?;
+    let cwd = service.working_directory.clone();
+    let arg_cptr: Vec<&CStr> = arg_cstrings.iter().map(|c| c.as_c_str()).collect();
+    let env_cptr: Vec<&CStr> = env_cstrings.iter().map(|c| c.as_c_str()).collect();
+    let (error_read, error_write) = unistd::pipe2(fcntl::OFlag::O_CLOEXEC)?;
+    let (status_read, status_write) = unistd::pipe2(fcntl::OFlag::O_CLOEXEC)?;
+    match unsafe { fork() } {
+        Ok(ForkResult::Child) => {
+            drop(error_read);
+            drop(status_read);
+            // This process never execs, so the descriptors inherited from Horust would stay open
+            // until the hook exits, even the O_CLOEXEC ones: e.g. the error pipes of the other
+            // services being spawned, whose spawn would wait for the hook.
+            close_fds_except([error_write.as_raw_fd(), status_write.as_raw_fd()]);
+            match unsafe { fork() } {
+                Ok(ForkResult::Child) => {
+                    drop(status_write);
+                    child_process_main(
+                        service,
+                        path,
+                        cwd,
+                        &credentials,
+                        arg_cptr,
+                        env_cptr,
+                        &error_write,
+                    );
+                    unreachable!();
+                }
+                Ok(ForkResult::Parent { child, .. }) => {
+                    drop(error_write);
+                    let _ = unistd::write(&status_write, &child.as_raw().to_le_bytes());
+                    let status = loop {
+                        match waitpid(child, None) {
+                            Err(Errno::EINTR) => continue,
+                            Ok(WaitStatus::Exited(_, code)) => break (0, code),
+                            Ok(WaitStatus::Signaled(_, signal, false)) => break (1, signal as i32),
+                            Ok(WaitStatus::Signaled(_, signal, true)) => break (2, signal as i32),
+                            _ => break (3, 0),
+                        }
+                    };
+                    let mut message = [status.0; HOOK_STATUS_LEN];
+                    message[1..].copy_from_slice(&status.1.to_le_bytes());
+                    let _ = unistd::write(&status_write, &message);
+                    unsafe { libc::_exit(0) }
+                }
+                Err(_) => unsafe { libc::_exit(1) },
+            }
+        }
+        Ok(ForkResult::Parent { .. }) => {
+            drop(error_write);
+            drop(status_write);
+            let mut status_read = File::from(status_read);
+            let mut hook_pid = [0; 4];
+            status_read
+                .read_exact(&mut hook_pid)
+                .map_err(|_| anyhow!("Failed to fork the hook process"))?;
+            let hook_pid = Pid::from_raw(i32::from_le_bytes(hook_pid));
+            let mut message = vec![];
+            File::from(error_read).read_to_end(&mut message)?;
+            if !message.is_empty() {
+                return Err(match decode_child_error(&message) {
+                    Some((step, errno)) => anyhow!("{}: {}", step.description(), errno),
+                    None => anyhow!("Invalid message from the hook process: {:?}", message),
+                });
+            }
+            let (sender, receiver) = bounded(1);
+            std::thread::spawn(move || {
+                let mut message = vec![];
+                let _ = status_read.read_to_end(&mut message);
+                let _ = sender.send(message);
+            });
+            let message = match receiver.recv_timeout(timeout) {
+                Ok(message) => message,
+                Err(_) => {
+                    // The hook leads its own process group, as it has created a new session.
+                    let _ = signal::killpg(hook_pid, signal::SIGKILL);
+                    bail!("Timed out after {:?}", timeout);
+                }
+            };
+            match decode_hook_status(&message) {
+                Some(ExitReason::Exited(0)) => Ok(()),
+                Some(reason) => bail!("The hook {}", reason),
+                None => bail!("Invalid exit status of the hook process: {:?}", message),
+            }
+        }
         Err(err) => Err(Into::into(err)),
     }
 }
 
+/// Decodes the exit status sent by the intermediate process of a hook.
+fn decode_hook_status(message: &[u8]) -> Option<ExitReason> {
+    if message.len() != HOOK_STATUS_LEN {
+        return None;
+    }
+    let value = i32::from_le_bytes(message[1..].try_into().ok()?);
+    match message[0] {
+        0 => Some(ExitReason::Exited(value)),
+        1 => Some(ExitReason::Signaled(Signal::try_from(value).ok()?)),
+        2 => Some(ExitReason::CoreDumped(Signal::try_from(value).ok()?)),
+        _ => None,
+    }
+}
+
+/// Closes every file descriptor but the standard ones and the `keep` ones.
+///
+/// # Safety
+///
+/// Use only async-signal-safe, otherwise it might lock.
+fn close_fds_except<const N: usize>(mut keep: [RawFd; N]) {
+    keep.sort_unstable();
+    let mut first = 3;
+    for fd in keep {
+        if fd < first {
+            continue;
+        }
+        if fd > first {
+            close_fds(first, fd - 1);
+        }
+        first = fd + 1;
+    }
+    close_fds(first, RawFd::MAX);
+}
+
+/// Closes the file descriptors from `first` to `last`, both included.
+fn close_fds(first: RawFd, last: RawFd) {
+    let (first_arg, last_arg) = (first as libc::c_uint, last as libc::c_uint);
+    if unsafe { libc::syscall(libc::SYS_close_range, first_arg, last_arg, 0) } == -1 {
+        // close_range is available since Linux 5.9. Otherwise, close up to the highest
+        // descriptor that can be open.
+        let max = resource::getrlimit(resource::Resource::RLIMIT_NOFILE)
+            .map_or(1024, |(soft, _)| soft.min(RawFd::MAX as u64) as RawFd);
+        for fd in first..=last.min(max - 1) {
+            unsafe { libc::close(fd) };
+        }
+    }
+}
+
 /// Sets up the stdout / stderr descriptors.
 fn redirect_output(
     target_stream: &LogOutput,
@@ -273,13 +733,17 @@ fn chunked_writer(fd: OwnedFd, service: Service) -> Result<()> {
 
 /// Find program on PATH.
 ///
-pub(crate) fn find_program(program_name: &String) -> Result<String> {
+pub(crate) fn find_program(program_name: &str) -> Result<String> {
     let path_var = match std::env::var_os("PATH") {
         Some(val) => val,
         None => return Err(anyhow!("PATH environment variable is not set")),
     };
+    find_program_in(program_name, &path_var)
+}
 
-    let paths: Vec<PathBuf> = std::env::split_paths(&path_var).collect();
+/// Find program in the directories listed in `path_var`, e.g. the PATH of a service.
+pub(crate) fn find_program_in(program_name: &str, path_var: &OsStr) -> Result<String> {
+    let paths: Vec<PathBuf> = std::env::split_paths(path_var).collect();
 
     for path in paths {
         let program_path = path.join(program_name);
@@ -306,15 +770,171 @@ fn exec(
     path: CString,
     arg_cptr: Vec<&CStr>,
     env_cptr: Vec<&CStr>,
-    uid: Uid,
+    rlimits: &Rlimits,
+    process: &ProcessAttributes,
+    credentials: &Credentials,
     cwd: PathBuf,
-) -> std::result::Result<(), Errno> {
+) -> std::result::Result<(), (ChildStep, Errno)> {
     // Changes the current working directory to the specified path.
-    unistd::chdir(&cwd)?;
+    unistd::chdir(&cwd).map_err(|errno| (ChildStep::Chdir, errno))?;
     // Create new session and set process group id
-    unistd::setsid()?;
+    unistd::setsid().map_err(|errno| (ChildStep::Setsid, errno))?;
+    // Set the limits while still privileged, as raising a hard limit needs it.
+    for (resource, _, rlimit) in rlimits.all() {
+        if let Some(rlimit) = rlimit {
+            resource::setrlimit(resource, rlimit.soft.raw(), rlimit.hard.raw())
+                .map_err(|errno| (ChildStep::Setrlimit, errno))?;
+        }
+    }
+    // Also while still privileged, as raising the priorities needs it.
+    set_process_attributes(process)?;
+    // Set the groups first: they can't be changed anymore once the user is not root.
+    if let Some(groups) = &credentials.groups {
+        unistd::setgroups(groups).map_err(|errno| (ChildStep::Setgroups, errno))?;
+    }
+    if let Some(gid) = credentials.gid {
+        unistd::setgid(gid).map_err(|errno| (ChildStep::Setgid, errno))?;
+    }
     // Set the user ID
-    unistd::setuid(uid)?;
-    unistd::execve(path.as_ref(), arg_cptr.as_ref(), env_cptr.as_ref())?;
+    unistd::setuid(credentials.uid).map_err(|errno| (ChildStep::Setuid, errno))?;
+    unistd::execve(path.as_ref(), arg_cptr.as_ref(), env_cptr.as_ref())
+        .map_err(|errno| (ChildStep::Exec, errno))?;
+    Ok(())
+}
+
+/// `ioprio_set` arguments, see linux/ioprio.h.
+const IOPRIO_WHO_PROCESS: libc::c_int = 1;
+const IOPRIO_CLASS_SHIFT: i32 = 13;
+
+/// Sets the scheduling attributes and the umask of the current process.
+///
+/// # Safety
+///
+/// Use only async-signal-safe, otherwise it might lock.
+fn set_process_attributes(
+    attributes: &ProcessAttributes,
+) -> std::result::Result<(), (ChildStep, Errno)> {
+    if let Some(nice) = attributes.nice {
+        if unsafe { libc::setpriority(libc::PRIO_PROCESS, 0, nice) } == -1 {
+            return Err((ChildStep::Nice, Errno::last()));
+        }
+    }
+    if let Some((class, priority)) = attributes.io_priority() {
+        let ioprio = (class.raw() << IOPRIO_CLASS_SHIFT) | i32::from(priority);
+        if unsafe { libc::syscall(libc::SYS_ioprio_set, IOPRIO_WHO_PROCESS, 0, ioprio) } == -1 {
+            return Err((ChildStep::Ionice, Errno::last()));
+        }
+    }
+    if !attributes.cpu_affinity.is_empty() {
+        let mut cpu_set = CpuSet::new();
+        for cpu in &attributes.cpu_affinity {
+            cpu_set
+                .set(*cpu)
+                .map_err(|errno| (ChildStep::CpuAffinity, errno))?;
+        }
+        sched_setaffinity(Pid::from_raw(0), &cpu_set)
+            .map_err(|errno| (ChildStep::CpuAffinity, errno))?;
+    }
+    if let Some(oom_score_adj) = attributes.oom_score_adj {
+        write_oom_score_adj(oom_score_adj).map_err(|errno| (ChildStep::OomScoreAdj, errno))?;
+    }
+    if let Some(umask) = attributes.umask {
+        stat::umask(Mode::from_bits_truncate(umask.0));
+    }
+    Ok(())
+}
+
+/// Writes the OOM score adjustment of the current process, without allocating.
+fn write_oom_score_adj(value: i32) -> nix::Result<()> {
+    // Enough for any i32.
+    let mut buffer = [0u8; 12];
+    let capacity = buffer.len();
+    let mut cursor = &mut buffer[..];
+    let _ = write!(cursor, "{}", value);
+    let len = capacity - cursor.len();
+    let fd = fcntl::open(
+        "/proc/self/oom_score_adj",
+        fcntl::OFlag::O_WRONLY | fcntl::OFlag::O_CLOEXEC,
+        Mode::empty(),
+    )?;
+    let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+    unistd::write(&fd, &buffer[..len])?;
+    Ok(())
+}
+
+/// Like `fork`, but the child is created in new namespaces. Unlike `unshare`, the child itself
+/// is moved into the new PID namespace, where it's the init process.
+///
+/// # Safety
+///
+/// Like `fork`: the child can only use async-signal-safe functions.
+unsafe fn fork_in_namespaces(namespaces: CloneFlags) -> nix::Result<ForkResult> {
+    // Without CLONE_VM and with no new stack, the child runs on a copy of the parent's memory,
+    // like with `fork`. The other arguments are unused with these flags.
+    let flags = namespaces.bits() as libc::c_long | libc::SIGCHLD as libc::c_long;
+    let res = libc::syscall(libc::SYS_clone, flags, 0, 0, 0, 0);
+    Errno::result(res).map(|res| match res {
+        0 => ForkResult::Child,
+        child => ForkResult::Parent {
+            child: Pid::from_raw(child as libc::pid_t),
+        },
+    })
+}
+
+/// Sets up the namespaces the child has been created in: the mounts, the loopback interface and
+/// the hostname.
+///
+/// # Safety
+///
+/// Use only async-signal-safe, otherwise it might lock.
+fn setup_sandbox(sandbox: &Sandbox) -> nix::Result<()> {
+    const NONE: Option<&str> = None;
+    const TMPFS: Option<&str> = Some("tmpfs");
+    let namespaces = sandbox.clone_flags();
+    if namespaces.contains(CloneFlags::CLONE_NEWNS) {
+        // Keep the mounts below out of the namespace of Horust.
+        let private = MsFlags::MS_REC | MsFlags::MS_PRIVATE;
+        mount(NONE, "/", NONE, private, NONE)?;
+        if namespaces.contains(CloneFlags::CLONE_NEWPID) {
+            // Otherwise /proc would show the processes of the parent namespace.
+            let flags = MsFlags::MS_NOSUID | MsFlags::MS_NODEV | MsFlags::MS_NOEXEC;
+            mount(Some("proc"), "/proc", Some("proc"), flags, NONE)?;
+        }
+        for path in &sandbox.read_only_paths {
+            let flags = MsFlags::MS_BIND | MsFlags::MS_REC;
+            mount(Some(path), path, NONE, flags, NONE)?;
+            let flags = MsFlags::MS_BIND | MsFlags::MS_REMOUNT | MsFlags::MS_RDONLY;
+            mount(NONE, path, NONE, flags, NONE)?;
+        }
+        if sandbox.private_tmp {
+            let flags = MsFlags::MS_NOSUID | MsFlags::MS_NODEV;
+            mount(TMPFS, "/tmp", TMPFS, flags, Some("mode=1777"))?;
+        }
+        if sandbox.hide_home {
+            let flags = MsFlags::MS_NOSUID | MsFlags::MS_NODEV | MsFlags::MS_RDONLY;
+            mount(TMPFS, "/home", TMPFS, flags, Some("mode=755"))?;
+        }
+    }
+    if namespaces.contains(CloneFlags::CLONE_NEWNET) {
+        loopback_up()?;
+    }
+    if let Some(hostname) = &sandbox.hostname {
+        let res = unsafe { libc::sethostname(hostname.as_ptr().cast(), hostname.len()) };
+        Errno::result(res)?;
+    }
+    Ok(())
+}
+
+/// Brings up the loopback interface, which is down in a new network namespace.
+fn loopback_up() -> nix::Result<()> {
+    let fd = unsafe { libc::socket(libc::AF_INET, libc::SOCK_DGRAM | libc::SOCK_CLOEXEC, 0) };
+    let fd = unsafe { OwnedFd::from_raw_fd(Errno::result(fd)?) };
+    let mut request: libc::ifreq = unsafe { std::mem::zeroed() };
+    for (dst, src) in request.ifr_name.iter_mut().zip(b"lo") {
+        *dst = *src as libc::c_char;
+    }
+    Errno::result(unsafe { libc::ioctl(fd.as_raw_fd(), libc::SIOCGIFFLAGS, &mut request) })?;
+    unsafe { request.ifr_ifru.ifru_flags |= libc::IFF_UP as libc::c_short };
+    Errno::result(unsafe { libc::ioctl(fd.as_raw_fd(), libc::SIOCSIFFLAGS, &request) })?;
     Ok(())
 }
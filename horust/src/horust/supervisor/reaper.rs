@@ -6,6 +6,7 @@
 use nix::sys::wait::{waitpid, WaitPidFlag, WaitStatus};
 use nix::unistd::Pid;
 
+use crate::horust::formats::ExitReason;
 use crate::horust::supervisor::repo::Repo;
 use crate::horust::Event;
 
@@ -23,14 +24,19 @@ pub(crate) fn run(repo: &Repo, max_iterations: u32) -> Vec<Event> {
                     WaitStatus::Exited(pid, exit_code) => {
                         debug!("Pid '{}' has exited with status: {}", pid, exit_code);
                         repo.get_service_by_pid(pid)
-                            .map(|s_name| (s_name, exit_code))
+                            .map(|s_name| (s_name, ExitReason::Exited(exit_code)))
                     }
                     WaitStatus::Signaled(pid, signal, core_dumped) => {
                         debug!(
                             "Pid '{:?}' has exited due to signal: {:?}. Core dumped: {}",
                             pid, signal, core_dumped
                         );
-                        repo.get_service_by_pid(pid).map(|s_name| (s_name, -137))
+                        let reason = if core_dumped {
+                            ExitReason::CoreDumped(signal)
+                        } else {
+                            ExitReason::Signaled(signal)
+                        };
+                        repo.get_service_by_pid(pid).map(|s_name| (s_name, reason))
                     }
                     _ => None,
                 },
@@ -50,9 +56,9 @@ pub(crate) fn run(repo: &Repo, max_iterations: u32) -> Vec<Event> {
                 }
             },
         )
-        .map(|(sname, exit_code)| {
+        .map(|(sname, reason)| {
             debug!("Service '{:?}' has exited.", sname);
-            Event::new_service_exited(sname.into(), exit_code)
+            Event::new_service_exited(sname.into(), reason)
         })
         .collect()
 }
@@ -1,9 +1,12 @@
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
+use nix::errno::Errno;
+use nix::sys::signal;
 use nix::unistd::Pid;
 
 use crate::horust::formats::{
-    FailureStrategy, HealthinessStatus, RestartStrategy, Service, ServiceName, ServiceStatus,
+    cgroup_pids, ExitReason, FailureStrategy, HealthinessStatus, HookKind, KillMode,
+    RestartStrategy, Service, ServiceName, ServiceStatus,
 };
 use crate::horust::supervisor::repo::Repo;
 use crate::horust::Event;
@@ -23,6 +26,19 @@ pub(crate) struct ServiceHandler {
     pub(super) healthiness_checks_failed: Option<i32>,
     /// Instant representing at which time we received a shutdown request. Will be used for comparing Service.termination.wait
     pub(super) shutting_down_start: Option<Instant>,
+    /// Process group of this service, led by its main process. It's kept after the main process
+    /// has exited, until the rest of the group is gone.
+    pub(super) pgid: Option<Pid>,
+    /// Exit reason of the main process, while the rest of its group is still running.
+    pub(super) exit_reason: Option<ExitReason>,
+    /// The step of the termination sequence whose signal was sent last.
+    pub(super) termination_step: usize,
+    /// The lifecycle hook being run, if any.
+    pub(super) running_hook: Option<HookKind>,
+    /// The lifecycle hook which has finished last, so each one is run once per transition.
+    pub(super) finished_hook: Option<HookKind>,
+    /// A hook has failed the start of this service, so its exit is a failure.
+    pub(super) start_failed: bool,
 }
 
 impl From<Service> for ServiceHandler {
@@ -116,6 +132,18 @@ impl ServiceHandler {
     pub fn shutting_down_started(&mut self) {
         self.shutting_down_start = Some(Instant::now());
     }
+
+    /// Returns true if no process other than the main one is left, according to the kill mode.
+    pub fn is_group_empty(&self) -> bool {
+        match self.service.termination.kill_mode {
+            KillMode::Process => true,
+            KillMode::ProcessGroup => self
+                .pgid
+                .map_or(true, |pgid| signal::killpg(pgid, None) == Err(Errno::ESRCH)),
+            // If the cgroup cannot be read, the service was not moved into it.
+            KillMode::Cgroup => cgroup_pids(self.name()).map_or(true, |pids| pids.is_empty()),
+        }
+    }
 }
 
 /// Generates events that, if applied, will make service_handler FSM progress
@@ -124,6 +152,16 @@ pub(crate) fn next(
     repo: &Repo,
     lifecycle_status: LifecycleStatus,
 ) -> Vec<Event> {
+    if let Some(reason) = service_handler.exit_reason {
+        // The main process has already exited: report it once the rest of the group is gone,
+        // and the hook being run has finished.
+        if service_handler.running_hook.is_none() && service_handler.is_group_empty() {
+            return vec![Event::new_service_exited(
+                service_handler.name().clone(),
+                reason,
+            )];
+        }
+    }
     match lifecycle_status {
         LifecycleStatus::Running => next_events(repo, service_handler),
         LifecycleStatus::ShuttingDown(shutting_down) => {
@@ -180,6 +218,12 @@ fn next_events(repo: &Repo, service_handler: &ServiceHandler) -> Vec<Event> {
             failure_evs.extend(other_services_termination);
             failure_evs
         }
+        ServiceStatus::InKilling if should_send_next_signal(service_handler, None) => {
+            vec![Event::new_termination_step(
+                service_handler.name(),
+                service_handler.termination_step + 1,
+            )]
+        }
         ServiceStatus::InKilling if should_force_kill(service_handler, None) => vec![
             Event::new_force_kill(service_handler.name()),
             Event::new_status_changed(service_handler.name(), ServiceStatus::Failed),
@@ -207,6 +251,12 @@ fn next_events_shutting_down(
         ],
         ServiceStatus::Success | ServiceStatus::Initial => vev_status(ServiceStatus::Finished),
         ServiceStatus::Failed => vev_status(ServiceStatus::FinishedFailed),
+        ServiceStatus::InKilling if should_send_next_signal(service_handler, shutting_down) => {
+            vec![Event::new_termination_step(
+                service_handler.name(),
+                service_handler.termination_step + 1,
+            )]
+        }
         ServiceStatus::InKilling if should_force_kill(service_handler, shutting_down) => {
             vec![Event::new_force_kill(service_handler.name())]
         }
@@ -339,7 +389,7 @@ fn should_force_kill(
     service_handler: &ServiceHandler,
     shutting_down: impl Into<Option<ShuttingDown>>,
 ) -> bool {
-    if service_handler.pid.is_none() {
+    if service_handler.pid.is_none() && service_handler.exit_reason.is_none() {
         // Since it was in the started state, it doesn't have a pid yet.
         // Let's give it the time to start and exit.
         return false;
@@ -350,13 +400,14 @@ fn should_force_kill(
     }
     if let Some(shutting_down_elapsed_secs) = service_handler.shutting_down_start {
         let shutting_down_elapsed_secs = shutting_down_elapsed_secs.elapsed().as_secs();
+        let total_wait = service_handler.service().termination.total_wait();
         debug!(
             "{}, should not force kill. Elapsed: {}, termination wait: {}",
             service_handler.name(),
             shutting_down_elapsed_secs,
-            service_handler.service().termination.wait.clone().as_secs()
+            total_wait.as_secs()
         );
-        shutting_down_elapsed_secs > service_handler.service().termination.wait.clone().as_secs()
+        shutting_down_elapsed_secs > total_wait.as_secs()
     } else {
         // this might happen, because InKilling state is emitted before the Kill event.
         // So maybe the supervisor has received only the InKilling state change, but hasn't sent the
@@ -366,6 +417,29 @@ fn should_force_kill(
     }
 }
 
+/// Check if the wait of the current termination step is over, and there is a next one.
+/// Shutting down forcefully skips the remaining steps.
+fn should_send_next_signal(
+    service_handler: &ServiceHandler,
+    shutting_down: impl Into<Option<ShuttingDown>>,
+) -> bool {
+    if service_handler.pid.is_none() && service_handler.exit_reason.is_none() {
+        return false;
+    }
+    if let Some(ShuttingDown::Forcefully) = shutting_down.into() {
+        return false;
+    }
+    let steps = service_handler.service().termination.steps();
+    let next_step = service_handler.termination_step + 1;
+    match service_handler.shutting_down_start {
+        Some(start) if next_step < steps.len() => {
+            let next_step_start: Duration = steps[..next_step].iter().map(|step| step.wait).sum();
+            start.elapsed() >= next_step_start
+        }
+        _ => false,
+    }
+}
+
 #[cfg(test)]
 mod test {
     use std::ops::Sub;
@@ -376,7 +450,8 @@ mod test {
 
     use crate::horust::formats::{FailureStrategy, Service, ServiceStatus, ShuttingDown};
     use crate::horust::supervisor::service_handler::{
-        handle_failed_service, handle_restart_strategy, should_force_kill, ServiceHandler,
+        handle_failed_service, handle_restart_strategy, should_force_kill, should_send_next_signal,
+        ServiceHandler,
     };
     use crate::horust::Event;
 
@@ -432,6 +507,34 @@ wait = "10s"
         assert!(should_force_kill(&sh, None));
     }
 
+    #[test]
+    fn test_termination_steps() {
+        let service = r#"command="notrelevant"
+[termination]
+steps = [{ signal = "INT", wait = "5s" }, { signal = "TERM", wait = "10s" }]
+"#;
+        let service: Service = toml::from_str(service).unwrap();
+        let mut sh: ServiceHandler = service.into();
+        sh.pid = Some(Pid::this());
+        sh.status = ServiceStatus::InKilling;
+        sh.shutting_down_started();
+        let start = sh.shutting_down_start.unwrap();
+        assert!(!should_send_next_signal(&sh, None));
+
+        sh.shutting_down_start = Some(start.sub(Duration::from_secs(6)));
+        assert!(should_send_next_signal(&sh, None));
+        assert!(!should_send_next_signal(&sh, ShuttingDown::Forcefully));
+        assert!(!should_force_kill(&sh, None));
+        sh.termination_step = 1;
+        assert!(!should_send_next_signal(&sh, None));
+
+        // SIGKILL only after the wait of every step.
+        sh.shutting_down_start = Some(start.sub(Duration::from_secs(12)));
+        assert!(!should_force_kill(&sh, None));
+        sh.shutting_down_start = Some(start.sub(Duration::from_secs(16)));
+        assert!(should_force_kill(&sh, None));
+    }
+
     #[test]
     fn test_handle_failed_service() {
         let mut service = Service::from_name("b");
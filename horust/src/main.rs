@@ -2,16 +2,25 @@ use std::path::PathBuf;
 
 use anyhow::{Context, Result};
 use clap::Parser;
-use horust::horust::{ExitStatus, HorustConfig};
+use horust::horust::formats::Service;
+use horust::horust::{check_services, load_services, ExitStatus, HorustConfig};
 use horust::Horust;
 use log::{error, info};
 use nix::unistd::getpid;
 
+const DEFAULT_SERVICES_PATH: &str = "/etc/horust/services";
+/// Env var with the services paths, used if none is given in the cmdline.
+const SERVICES_PATH_ENV: &str = "HORUST_SERVICES_PATH";
+
 #[derive(clap::Parser, Debug)]
 #[clap(author, about)]
 /// Horust is a complete supervisor and init system, designed for running in containers.
 struct Opts {
-    #[arg(long, default_value = "/etc/horust/horust.toml")]
+    #[arg(
+        long,
+        env = "HORUST_CONFIG_PATH",
+        default_value = "/etc/horust/horust.toml"
+    )]
     /// Horust's path to config.
     config_path: PathBuf,
 
@@ -22,17 +31,69 @@ struct Opts {
     /// Print a sample service file with all the possible options
     sample_service: bool,
 
-    #[arg(long = "services-path", default_value = "/etc/horust/services")]
+    #[arg(
+        long,
+        value_enum,
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "toml"
+    )]
+    /// Print the services as they would be run (templated, with defaults and names), then exit.
+    print_config: Option<ConfigFormat>,
+
+    #[arg(long = "services-path")]
     /// Path to service file or a directory containing services to run. You can provide more than one argument to load multiple directories / services.
+    /// If missing, the `HORUST_SERVICES_PATH` env var is used, with multiple paths separated by `:`.
+    /// Loaded together with the `services-paths` of the config file. [default: /etc/horust/services]
     services_paths: Vec<PathBuf>,
 
-    #[arg(required = false, long, default_value = "/var/run/horust")]
+    #[arg(
+        required = false,
+        long,
+        env = "HORUST_UDS_FOLDER_PATH",
+        default_value = "/var/run/horust"
+    )]
     /// Path to the folder that contains the Unix Domain Socket, used to communicate with horustctl
     uds_folder_path: PathBuf,
 
     #[arg(required = false, last = true)]
     /// Specify a command to run instead of load services path. Useful if you just want to use the reaping capability. Prefix your command with --
     command: Vec<String>,
+
+    #[command(subcommand)]
+    subcommand: Option<Subcommand>,
+}
+
+#[derive(clap::Subcommand, Debug)]
+enum Subcommand {
+    /// Check the services found in the services paths without running them.
+    /// Exits with an unsuccessful exit code if any problem is found.
+    Validate(ValidateArgs),
+}
+
+#[derive(clap::Args, Debug)]
+struct ValidateArgs {
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    /// Output format of the found problems.
+    format: OutputFormat,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+enum OutputFormat {
+    Text,
+    Json,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+enum ConfigFormat {
+    Toml,
+    Json,
+}
+
+/// The printed configuration: it has the same shape of the inline services in `horust.toml`.
+#[derive(serde::Serialize)]
+struct EffectiveConfig<'a> {
+    service: &'a [Service],
 }
 
 fn main() -> Result<()> {
@@ -56,6 +117,34 @@ fn main() -> Result<()> {
                 &opts.config_path.display()
             )
         })?;
+    let services_paths = services_paths(&opts, &config);
+
+    if let Some(format) = opts.print_config {
+        let services = load_services(&config, &services_paths)?;
+        let config = EffectiveConfig { service: &services };
+        match format {
+            ConfigFormat::Toml => print!("{}", toml::to_string(&config)?),
+            ConfigFormat::Json => println!("{}", serde_json::to_string_pretty(&config)?),
+        }
+        return Ok(());
+    }
+
+    if let Some(Subcommand::Validate(args)) = &opts.subcommand {
+        let problems = check_services(&opts.config_path, &config, &services_paths);
+        match args.format {
+            OutputFormat::Text if problems.is_empty() => println!("No problems found."),
+            OutputFormat::Text => {
+                problems.iter().for_each(|problem| println!("{}", problem));
+                println!("Found {} problem(s).", problems.len());
+            }
+            OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&problems)?),
+        }
+        if !problems.is_empty() {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
     if !opts.uds_folder_path.exists() {
         std::fs::create_dir_all(&opts.uds_folder_path).with_context(|| {
             format!(
@@ -76,12 +165,12 @@ fn main() -> Result<()> {
     let mut horust = if opts.command.is_empty() {
         info!(
             "Loading services from {}",
-            display_directories(&opts.services_paths)
+            display_directories(&services_paths)
         );
-        Horust::from_services_dirs(&opts.services_paths, uds_path).with_context(|| {
+        Horust::from_config(&config, &services_paths, uds_path).with_context(|| {
             format!(
                 "Failed loading services from {}",
-                display_directories(&opts.services_paths)
+                display_directories(&services_paths)
             )
         })?
     } else {
@@ -90,7 +179,7 @@ fn main() -> Result<()> {
     };
 
     if let ExitStatus::SomeServiceFailed = horust.run() {
-        if config.unsuccessful_exit_finished_failed {
+        if config.unsuccessful_exit_finished_failed() {
             error!("Some processes have failed.");
             std::process::exit(101);
         }
@@ -98,6 +187,28 @@ fn main() -> Result<()> {
     Ok(())
 }
 
+/// The services paths from both cmdline and config file. If no services are defined at all, the
+/// default services path is used.
+fn services_paths(opts: &Opts, config: &HorustConfig) -> Vec<PathBuf> {
+    let cmdline_paths: Vec<PathBuf> = if opts.services_paths.is_empty() {
+        // Only the env var is split: a path given in the cmdline can contain `:`.
+        std::env::var_os(SERVICES_PATH_ENV)
+            .map(|paths| std::env::split_paths(&paths).collect())
+            .unwrap_or_default()
+    } else {
+        opts.services_paths.clone()
+    };
+    let mut paths: Vec<PathBuf> = cmdline_paths
+        .into_iter()
+        .filter(|path| !path.as_os_str().is_empty())
+        .chain(config.services_paths.iter().cloned())
+        .collect();
+    if paths.is_empty() && config.services.is_empty() {
+        paths.push(PathBuf::from(DEFAULT_SERVICES_PATH));
+    }
+    paths
+}
+
 fn display_directories(dirs: &[PathBuf]) -> String {
     match dirs.len() {
         1 => format!("directory: {}", dirs.first().unwrap().display()),
@@ -1,4 +1,5 @@
 use assert_cmd::prelude::*;
+use predicates::prelude::*;
 use predicates::str::contains;
 use rand::distributions::Alphanumeric;
 use rand::{thread_rng, Rng};
@@ -26,6 +27,16 @@ exit 1
     let cmd = cmd.args(vec!["--unsuccessful-exit-finished-failed"]);
     let recv = run_async(cmd, false);
     recv.recv_or_kill(Duration::from_secs(15));
+
+    // Same via env, and the cmdline can explicitly disable it.
+    let (mut cmd, temp_dir) = get_cli();
+    store_service_script(temp_dir.path(), failing_script, None, None);
+    cmd.env("HORUST_UNSUCCESSFUL_EXIT_FINISHED_FAILED", "true");
+    let recv = run_async(&mut cmd, false);
+    recv.recv_or_kill(Duration::from_secs(15));
+    let cmd = cmd.args(vec!["--unsuccessful-exit-finished-failed=false"]);
+    let recv = run_async(cmd, true);
+    recv.recv_or_kill(Duration::from_secs(15));
 }
 
 #[test]
@@ -66,6 +77,43 @@ fn test_single_command() {
         .stderr(contains("Failed spawning the process: Invalid command:"));
 }
 
+#[test]
+fn test_services_path_with_colon() {
+    // A path given in the cmdline is not split on `:`.
+    let (mut cmd, temp_dir) = get_cli();
+    let services_dir = temp_dir.path().join("services:1");
+    std::fs::create_dir(&services_dir).unwrap();
+    store_service(&services_dir, r#"command = "echo kilroy was here""#, None);
+    cmd.arg("--services-path").arg(&services_dir);
+    cmd.assert().success().stdout(contains("kilroy was here"));
+}
+
+#[test]
+fn test_services_path_env() {
+    // Without `--services-path`, the paths are taken from the env var, separated by `:`.
+    let temp_dir = tempfile::TempDir::with_prefix("horust").unwrap();
+    let (first, second) = (
+        temp_dir.path().join("first"),
+        temp_dir.path().join("second"),
+    );
+    for (dir, word) in [(&first, "kilroy"), (&second, "wuz")] {
+        std::fs::create_dir(dir).unwrap();
+        store_service(dir, &format!(r#"command = "echo {word}""#), None);
+    }
+    let mut cmd = std::process::Command::cargo_bin("horust").unwrap();
+    cmd.current_dir(&temp_dir)
+        .arg("--uds-folder-path")
+        .arg(temp_dir.path())
+        .env(
+            "HORUST_SERVICES_PATH",
+            format!("{}:{}", first.display(), second.display()),
+        );
+    cmd.assert()
+        .success()
+        .stdout(contains("kilroy"))
+        .stdout(contains("wuz"));
+}
+
 #[test]
 fn test_multiple() {
     let (mut cmd, temp_dir, temp_dir_2) = get_cli_multiple();
@@ -115,3 +163,88 @@ fn test_stress_test_chained_services() {
     let recv = run_async(&mut cmd, true);
     recv.recv_or_kill(Duration::from_secs(max * 2));
 }
+
+#[test]
+fn test_validate() {
+    let (mut cmd, temp_dir) = get_cli();
+    let script = r#"#!/usr/bin/env bash
+:"#;
+    store_service_script(temp_dir.path(), script, None, Some("a"));
+    cmd.args(vec!["validate"]);
+    cmd.assert()
+        .success()
+        .stdout(contains("No problems found."));
+
+    let (mut cmd, temp_dir) = get_cli();
+    store_service_script(
+        temp_dir.path(),
+        script,
+        Some(r#"start-after = ["b.toml"]"#),
+        Some("a"),
+    );
+    store_service_script(
+        temp_dir.path(),
+        script,
+        Some(r#"start-after = ["a.toml"]"#),
+        Some("b"),
+    );
+    cmd.args(vec!["validate", "--format", "json"]);
+    cmd.assert()
+        .failure()
+        .stdout(contains(r#""field": "start-after""#))
+        .stdout(contains("a.toml -> b.toml -> a.toml").or(contains("b.toml -> a.toml -> b.toml")));
+}
+
+#[test]
+fn test_config_inline_services() {
+    let (mut cmd, temp_dir) = get_cli();
+    let config_path = temp_dir.path().join("horust.toml");
+    let config = r#"[[service]]
+name = "hello"
+command = "/usr/bin/env bash -c 'echo hello from config'"
+"#;
+    std::fs::write(&config_path, config).unwrap();
+    cmd.args(vec!["--config-path", config_path.to_str().unwrap()]);
+    cmd.assert().success().stdout(contains("hello from config"));
+
+    // Name collisions with file services are validation errors.
+    let (mut cmd, temp_dir) = get_cli();
+    let config_path = temp_dir.path().join("horust.toml");
+    std::fs::write(&config_path, config.replace("hello", "a.toml")).unwrap();
+    let script = r#"#!/usr/bin/env bash
+:"#;
+    store_service_script(temp_dir.path(), script, None, Some("a"));
+    cmd.args(vec![
+        "--config-path",
+        config_path.to_str().unwrap(),
+        "validate",
+    ]);
+    cmd.assert()
+        .failure()
+        .stdout(contains("is defined more than once"));
+}
+
+#[test]
+fn test_print_config() {
+    let (mut cmd, temp_dir) = get_cli();
+    let service = r#"command = "/bin/true"
+stdout-rotate-size = "1KB"
+[resource-limit]
+memory = "2 KB"
+"#;
+    std::fs::write(temp_dir.path().join("a.toml"), service).unwrap();
+    cmd.args(vec!["--print-config"]);
+    cmd.assert()
+        .success()
+        .stdout(contains("[[service]]"))
+        .stdout(contains(r#"name = "a.toml""#))
+        .stdout(contains("stdout-rotate-size = 1000"))
+        .stdout(contains("memory = 2000"));
+
+    let (mut cmd, temp_dir) = get_cli();
+    std::fs::write(temp_dir.path().join("a.toml"), service).unwrap();
+    cmd.args(vec!["--print-config=json"]);
+    cmd.assert()
+        .success()
+        .stdout(contains(r#""name": "a.toml""#));
+}
@@ -1,6 +1,7 @@
 use assert_cmd::prelude::*;
 use predicates::prelude::*;
 use predicates::str::contains;
+use std::process::Command;
 
 #[allow(dead_code)]
 mod utils;
@@ -76,3 +77,138 @@ re-export = [ "DB_PASS" ]
         .success()
         .stdout(contains("MyPassword"));
 }
+
+#[test]
+fn test_environment_env_files_and_from_file() {
+    let (mut cmd, temp_dir) = get_cli();
+    let env_file = temp_dir.path().join("app.env");
+    let secret_file = temp_dir.path().join("db_pass");
+    std::fs::write(
+        &env_file,
+        "APP_MODE=\"production\"\nDB_PASS=from-env-file\n",
+    )
+    .unwrap();
+    std::fs::write(&secret_file, "MySecretPassword\n").unwrap();
+    let service = format!(
+        r#"[environment]
+env-files = ["{}"]
+from-file = {{ DB_PASS = "{}" }}
+"#,
+        env_file.display(),
+        secret_file.display()
+    );
+    store_service_script(temp_dir.path(), ENVIRONMENT_SCRIPT, Some(&service), None);
+    cmd.assert()
+        .success()
+        .stdout(contains("APP_MODE=production"))
+        .stdout(contains("DB_PASS=MySecretPassword"))
+        .stdout(contains("from-env-file").not());
+
+    // Only the paths end up in the printed configuration.
+    Command::cargo_bin("horust")
+        .unwrap()
+        .args(vec![
+            "--services-path",
+            temp_dir.path().display().to_string().as_str(),
+            "--print-config",
+        ])
+        .assert()
+        .success()
+        .stdout(contains(secret_file.display().to_string()))
+        .stdout(contains("MySecretPassword").not());
+}
+
+#[test]
+fn test_environment_inherit() {
+    let (mut cmd, temp_dir) = get_cli();
+    let service = r#"[environment]
+inherit = [ "AWS_*", "LANG" ]
+"#;
+    store_service_script(temp_dir.path(), ENVIRONMENT_SCRIPT, Some(service), None);
+    cmd.env("AWS_REGION", "eu-west-1")
+        .env("LANG", "C.UTF-8")
+        .env("LANGUAGE", "en")
+        .env("DB_PASS", "MyPassword")
+        .assert()
+        .success()
+        .stdout(contains("AWS_REGION=eu-west-1"))
+        .stdout(contains("LANG=C.UTF-8"))
+        .stdout(contains("LANGUAGE").not())
+        .stdout(contains("MyPassword").not());
+}
+
+#[test]
+fn test_environment_unset() {
+    let (mut cmd, temp_dir) = get_cli();
+    let service = r#"[environment]
+keep-env = true
+unset = [ "*_TOKEN" ]
+"#;
+    store_service_script(temp_dir.path(), ENVIRONMENT_SCRIPT, Some(service), None);
+    cmd.env("GITHUB_TOKEN", "MyToken")
+        .env("DB_PASS", "MyPassword")
+        .assert()
+        .success()
+        .stdout(contains("MyToken").not())
+        .stdout(contains("MyPassword"));
+}
+
+#[test]
+fn test_environment_precedence() {
+    let (mut cmd, temp_dir) = get_cli();
+    // unset wins over keep-env, inherit and re-export, but not over additional.
+    let service = r#"[environment]
+keep-env = true
+inherit = [ "APP_*" ]
+re-export = [ "APP_REEXPORTED" ]
+unset = [ "APP_*" ]
+additional = { APP_ADDITIONAL = "bar" }
+"#;
+    store_service_script(temp_dir.path(), ENVIRONMENT_SCRIPT, Some(service), None);
+    cmd.env("APP_INHERITED", "inherited")
+        .env("APP_REEXPORTED", "reexported")
+        .env("APP_ADDITIONAL", "overridden")
+        .assert()
+        .success()
+        .stdout(contains("inherited").not())
+        .stdout(contains("reexported").not())
+        .stdout(contains("overridden").not())
+        .stdout(contains("APP_ADDITIONAL=bar"));
+}
+
+#[test]
+fn test_environment_unset_predefined() {
+    let (mut cmd, temp_dir) = get_cli();
+    // unset only removes the inherited variables: the predefined ones are kept.
+    let service = r#"[environment]
+keep-env = true
+unset = [ "*" ]
+"#;
+    store_service_script(
+        temp_dir.path(),
+        ENVIRONMENT_SCRIPT,
+        Some(service),
+        Some("unset"),
+    );
+    cmd.env("DB_PASS", "MyPassword")
+        .assert()
+        .success()
+        .stdout(contains("MyPassword").not())
+        .stdout(contains("HORUST_SERVICE_NAME=unset.toml"))
+        .stdout(contains("PATH="))
+        .stdout(contains("HOME="));
+}
+
+#[test]
+fn test_environment_supervisor_metadata() {
+    let (mut cmd, temp_dir) = get_cli();
+    store_service_script(temp_dir.path(), ENVIRONMENT_SCRIPT, None, Some("meta"));
+    let socket_path = format!("HORUST_SOCKET_PATH={}/horust-", temp_dir.path().display());
+    cmd.assert()
+        .success()
+        .stdout(contains("HORUST_SERVICE_NAME=meta.toml"))
+        .stdout(contains("HORUST_RESTART_COUNT=0"))
+        .stdout(contains("HORUST_PID="))
+        .stdout(contains(socket_path))
+        .stdout(contains("HORUST_INSTANCE").not());
+}
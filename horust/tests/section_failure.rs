@@ -1,3 +1,5 @@
+use assert_cmd::prelude::*;
+
 mod utils;
 use utils::*;
 
@@ -46,3 +48,26 @@ fn test_failure_shutdown() {
 fn test_failure_kill_dependents() {
     test_failure_strategy("kill-dependents");
 }
+
+fn test_killed_by_signal(failure: &str, should_fail: bool) {
+    let (mut cmd, temp_dir) = get_cli();
+    let script = r#"#!/usr/bin/env bash
+kill -TERM $$"#;
+    store_service_script(temp_dir.path(), script, Some(failure), None);
+    let assert = cmd.arg("--unsuccessful-exit-finished-failed").assert();
+    if should_fail {
+        assert.failure();
+    } else {
+        assert.success();
+    }
+}
+
+#[test]
+fn test_failure_killed_by_signal() {
+    test_killed_by_signal("", true);
+}
+
+#[test]
+fn test_failure_successful_signals() {
+    test_killed_by_signal("[failure]\nsuccessful-signals = [\"TERM\"]", false);
+}
@@ -4,6 +4,7 @@ use tempfile::TempDir;
 
 mod utils;
 use nix::sys::signal::{kill, Signal};
+use std::os::unix::fs::PermissionsExt;
 use std::thread::sleep;
 use std::time::Duration;
 use utils::*;
@@ -129,6 +130,70 @@ fn test_search_path_found() {
     cmd.assert().success().stdout(contains("kilroy was here"));
 }
 
+#[test]
+fn test_command_expansion() {
+    let (mut cmd, temp_dir) = get_cli();
+    // Variables are expanded from the service's environment, `$$` is a literal `$`.
+    let service = r#"command = "echo port=${PORT} greeting=$GREETING price=$$5 missing=${MISSING}"
+[environment]
+additional = { PORT = "8080", GREETING = "hello world" }
+"#;
+    store_service(temp_dir.path(), service, None);
+    cmd.assert().success().stdout(contains(
+        "port=8080 greeting=hello world price=$5 missing=${MISSING}",
+    ));
+}
+
+#[test]
+fn test_command_args() {
+    let (mut cmd, temp_dir) = get_cli();
+    let service = r#"command = ["/bin/sh", "-c", "echo \"$0\"", "kilroy  was here"]"#;
+    store_service(temp_dir.path(), service, None);
+    cmd.assert().success().stdout(contains("kilroy  was here"));
+}
+
+#[test]
+fn test_command_args_not_expanded() {
+    let (mut cmd, temp_dir) = get_cli();
+    // The elements of the list reach argv unchanged, neither templated nor expanded.
+    let service = r#"command = ["/bin/echo", "home=$HOME", "${HOME}", "$$"]"#;
+    store_service(temp_dir.path(), service, None);
+    cmd.assert()
+        .success()
+        .stdout(contains("home=$HOME ${HOME} $$\n"));
+}
+
+#[test]
+fn test_command_shell() {
+    let (mut cmd, temp_dir) = get_cli();
+    let service = r#"command = "echo kilroy | tr k K && echo $HORUST_SERVICE_NAME"
+shell = true
+"#;
+    store_service(temp_dir.path(), service, Some("shell.toml"));
+    cmd.assert()
+        .success()
+        .stdout(contains("Kilroy"))
+        .stdout(contains("shell.toml"));
+}
+
+#[test]
+fn test_search_path_from_service_environment() {
+    let (mut cmd, temp_dir) = get_cli();
+    let bin_dir = TempDir::with_prefix("bin").unwrap();
+    let program = bin_dir.path().join("kilroy-horust");
+    std::fs::write(&program, "#!/bin/sh\necho kilroy was here").unwrap();
+    std::fs::set_permissions(&program, std::fs::Permissions::from_mode(0o755)).unwrap();
+    let service = format!(
+        r#"command = "kilroy-horust"
+[environment]
+additional = {{ PATH = "{}" }}
+"#,
+        bin_dir.path().display()
+    );
+    store_service(temp_dir.path(), &service, None);
+    cmd.assert().success().stdout(contains("kilroy was here"));
+}
+
 #[test]
 fn test_cwd() {
     let (mut cmd, temp_dir) = get_cli();
@@ -141,6 +206,22 @@ pwd"#;
     cmd.assert().success().stdout(contains(displ.as_str()));
 }
 
+#[test]
+fn test_cwd_missing() {
+    let (mut cmd, temp_dir) = get_cli();
+    // The failed spawn is reported with its reason, and not seen as an exit code of the service.
+    let service = r#"command = "echo kilroy was here"
+working-directory = "/surely/not/existing/horust/cwd"
+[failure]
+successful-exit-code = [0, 103]
+"#;
+    store_service(temp_dir.path(), service, None);
+    cmd.arg("--unsuccessful-exit-finished-failed")
+        .assert()
+        .failure()
+        .stderr(contains("Failed to change the working directory: ENOENT"));
+}
+
 #[test]
 fn test_cwd_default() {
     let (mut cmd, temp_dir) = get_cli();
@@ -194,6 +275,123 @@ whoami"#;
     cmd.assert().success().stdout(contains("games"));
 }
 
+// Test group and supplementary groups
+#[test]
+#[ignore]
+fn test_group() {
+    // Needs to run as root, like test_user.
+    let (mut cmd, temp_dir) = get_cli();
+    let service = r#"group = 1234
+supplementary-groups = [5678]"#;
+    let script = r#"#!/usr/bin/env bash
+echo "gid: `id -g`, groups: `id -G`""#;
+    store_service_script(temp_dir.path(), script, Some(service), None);
+    cmd.assert()
+        .success()
+        .stdout(contains("gid: 1234, groups: 1234 5678"));
+}
+
+#[test]
+fn test_group_user_without_passwd_entry() {
+    // Without a passwd entry, the service would otherwise keep the group of Horust.
+    let (mut cmd, temp_dir) = get_cli();
+    let service = r#"command = "echo kilroy was here"
+user = 54321
+"#;
+    store_service(temp_dir.path(), service, None);
+    cmd.arg("--unsuccessful-exit-finished-failed")
+        .assert()
+        .failure()
+        .stderr(contains("User `54321` not found"));
+}
+
+#[test]
+fn test_group_missing() {
+    let (mut cmd, temp_dir) = get_cli();
+    let service = r#"command = "echo kilroy was here"
+group = "surely-not-a-group-horust"
+"#;
+    store_service(temp_dir.path(), service, None);
+    cmd.arg("--unsuccessful-exit-finished-failed")
+        .assert()
+        .failure()
+        .stderr(contains("Group `surely-not-a-group-horust` not found"));
+}
+
+#[test]
+fn test_rlimits() {
+    let (mut cmd, temp_dir) = get_cli();
+    let service = r#"[rlimits]
+nofile = { soft = 256, hard = 512 }
+stack = "4 MiB""#;
+    let script = r#"#!/usr/bin/env bash
+echo "nofile: `ulimit -Sn` `ulimit -Hn`, stack: `ulimit -Ss`""#;
+    store_service_script(temp_dir.path(), script, Some(service), None);
+    cmd.assert()
+        .success()
+        .stdout(contains("nofile: 256 512, stack: 4096"));
+}
+
+#[test]
+fn test_process_attributes() {
+    let (mut cmd, temp_dir) = get_cli();
+    let service = r#"[process]
+nice = 5
+io-class = "idle"
+cpu-affinity = [0]
+oom-score-adj = 500
+umask = "027""#;
+    let script = r#"#!/usr/bin/env bash
+echo "nice: `nice`"
+echo "io: `ionice`"
+grep Cpus_allowed_list /proc/self/status
+echo "oom: `cat /proc/self/oom_score_adj`"
+echo "umask: `umask`""#;
+    store_service_script(temp_dir.path(), script, Some(service), None);
+    cmd.assert()
+        .success()
+        .stdout(contains("nice: 5"))
+        .stdout(contains("io: idle"))
+        .stdout(contains("Cpus_allowed_list:\t0\n"))
+        .stdout(contains("oom: 500"))
+        .stdout(contains("umask: 0027"));
+}
+
+// Test sandbox
+#[test]
+#[ignore]
+fn test_sandbox() {
+    // Needs to run as root, like test_user.
+    let (mut cmd, temp_dir) = get_cli();
+    // The script lives in a temp dir under /tmp, so `private-tmp` would hide it.
+    let service = format!(
+        r#"[sandbox]
+namespaces = ["pid", "network", "ipc", "uts"]
+read-only-paths = ["{}"]
+hide-home = true
+hostname = "sandboxed""#,
+        temp_dir.path().display()
+    );
+    let script = format!(
+        r#"#!/usr/bin/env bash
+echo "ppid: `cut -d' ' -f4 /proc/self/stat`"
+echo "interfaces: `grep -c : /proc/net/dev`"
+echo "hostname: `hostname`"
+echo "home: `ls -A /home | wc -l`"
+touch {}/file 2> /dev/null || echo "read-only""#,
+        temp_dir.path().display()
+    );
+    store_service_script(temp_dir.path(), &script, Some(&service), None);
+    cmd.assert()
+        .success()
+        .stdout(contains("ppid: 1\n"))
+        .stdout(contains("interfaces: 1\n"))
+        .stdout(contains("hostname: sandboxed"))
+        .stdout(contains("home: 0"))
+        .stdout(contains("read-only"));
+    assert!(!temp_dir.path().join("file").exists());
+}
+
 #[test]
 fn test_termination_with_pending_thread() {
     // start-delay should not interfere with the shutting down.
@@ -0,0 +1,154 @@
+use assert_cmd::prelude::*;
+use nix::sys::signal::{kill, Signal};
+use std::thread::sleep;
+use std::time::Duration;
+
+mod utils;
+use utils::*;
+
+/// A hook appending its name to the `hooks.log` file, in the working directory of the service.
+fn log_hook(name: &str) -> String {
+    format!(r#"{{ command = ["/bin/sh", "-c", "echo {name} >> hooks.log"] }}"#)
+}
+
+#[test]
+fn test_hooks_lifecycle() {
+    let (mut cmd, temp_dir) = get_cli();
+    let script = r#"#!/usr/bin/env bash
+sleep 2"#;
+    let service = format!(
+        r#"[hooks]
+pre-start = {}
+post-start = {}
+pre-stop = {}
+post-stop = {}"#,
+        log_hook("pre-start"),
+        log_hook("post-start"),
+        log_hook("pre-stop"),
+        log_hook("post-stop")
+    );
+    store_service_script(temp_dir.path(), script, Some(&service), None);
+    cmd.assert().success();
+    // The service exits on its own, so it's never stopped.
+    let log = std::fs::read_to_string(temp_dir.path().join("hooks.log")).unwrap();
+    assert_eq!(log, "pre-start\npost-start\npost-stop\n");
+}
+
+#[test]
+fn test_hooks_pre_stop() {
+    let (mut cmd, temp_dir) = get_cli();
+    let script = r#"#!/usr/bin/env bash
+trap 'echo TERM >> hooks.log; exit 0' TERM
+while true ; do
+    sleep 0.3
+done"#;
+    let service = format!("[hooks]\npre-stop = {}", log_hook("pre-stop"));
+    store_service_script(temp_dir.path(), script, Some(&service), None);
+    let recv = run_async(&mut cmd, true);
+    sleep(Duration::from_secs(1));
+    kill(recv.pid, Signal::SIGINT).expect("kill");
+    recv.recv_or_kill(Duration::from_secs(10));
+    let log = std::fs::read_to_string(temp_dir.path().join("hooks.log")).unwrap();
+    assert_eq!(log, "pre-stop\nTERM\n");
+}
+
+#[test]
+fn test_hooks_slow_pre_start() {
+    // A slow pre-start hook must not hold back the other services.
+    let (mut cmd, temp_dir) = get_cli();
+    let service = r#"[hooks]
+pre-start = { command = ["/bin/sh", "-c", "ls -l /proc/$PPID/fd > hook-parent-fds; sleep 3"] }"#;
+    let script = r#"#!/usr/bin/env bash
+sleep 1"#;
+    store_service_script(temp_dir.path(), script, Some(service), None);
+    let script = r#"#!/usr/bin/env bash
+touch started
+sleep 1"#;
+    store_service_script(temp_dir.path(), script, None, None);
+    let recv = run_async(&mut cmd, true);
+    sleep(Duration::from_secs(1));
+    assert!(temp_dir.path().join("started").exists());
+    // The process waiting for the hook has closed the descriptors inherited from Horust, e.g.
+    // its socket or the error pipes of the other services.
+    let fds = std::fs::read_to_string(temp_dir.path().join("hook-parent-fds")).unwrap();
+    let inherited: Vec<&str> = fds
+        .lines()
+        .filter(|line| {
+            let fd = line
+                .split_whitespace()
+                .nth(8)
+                .and_then(|fd| fd.parse().ok());
+            fd.is_some_and(|fd: i32| fd > 2)
+        })
+        .collect();
+    // Only the pipe to report the status of the hook, and the error pipe until the hook execs.
+    assert!(inherited.len() <= 2, "{}", fds);
+    assert!(inherited.iter().all(|fd| fd.contains("pipe:")), "{}", fds);
+    kill(recv.pid, Signal::SIGINT).expect("kill");
+    recv.recv_or_kill(Duration::from_secs(10));
+}
+
+fn test_pre_start_failure(hook: &str) {
+    let (mut cmd, temp_dir) = get_cli();
+    let script = r#"#!/usr/bin/env bash
+touch started"#;
+    let service = format!("[hooks]\npre-start = {}", hook);
+    store_service_script(temp_dir.path(), script, Some(&service), None);
+    cmd.arg("--unsuccessful-exit-finished-failed");
+    let recv = run_async(&mut cmd, false);
+    recv.recv_or_kill(Duration::from_secs(10));
+    assert!(!temp_dir.path().join("started").exists());
+}
+
+#[test]
+fn test_hooks_pre_start_fail_start() {
+    test_pre_start_failure(r#"{ command = "false", on-failure = "fail-start" }"#);
+}
+
+#[test]
+fn test_hooks_timeout() {
+    test_pre_start_failure(
+        r#"{ command = "sleep 30", timeout = "1s", on-failure = "fail-start" }"#,
+    );
+}
+
+#[test]
+fn test_hooks_pre_start_ignore() {
+    let (mut cmd, temp_dir) = get_cli();
+    let script = r#"#!/usr/bin/env bash
+touch started"#;
+    let service = r#"[hooks]
+pre-start = { command = "false" }"#;
+    store_service_script(temp_dir.path(), script, Some(service), None);
+    cmd.arg("--unsuccessful-exit-finished-failed")
+        .assert()
+        .success();
+    assert!(temp_dir.path().join("started").exists());
+}
+
+#[test]
+fn test_hooks_pre_stop_abort_stop() {
+    let (mut cmd, temp_dir) = get_cli();
+    let script = r#"#!/usr/bin/env bash
+trap 'echo TERM >> hooks.log; exit 0' TERM
+while true ; do
+    sleep 0.3
+done"#;
+    // The stop requested by the failure of `a` is aborted, a shutdown is not.
+    let service = r#"[termination]
+die-if-failed = ["a.toml"]
+[hooks]
+pre-stop = { command = "false", on-failure = "abort-stop" }"#;
+    store_service_script(temp_dir.path(), script, Some(service), None);
+    let script = r#"#!/usr/bin/env bash
+sleep 1
+exit 1"#;
+    store_service_script(temp_dir.path(), script, None, Some("a"));
+    let recv = run_async(&mut cmd, true);
+    sleep(Duration::from_secs(4));
+    assert!(!temp_dir.path().join("hooks.log").exists());
+    kill(recv.pid, Signal::SIGINT).expect("kill");
+    recv.recv_or_kill(Duration::from_secs(10));
+    let log = std::fs::read_to_string(temp_dir.path().join("hooks.log")).unwrap();
+    assert_eq!(log, "TERM\n");
+}
@@ -0,0 +1,63 @@
+use horust_commands_lib::{get_path, ClientHandler, HorustMsgServiceStatus};
+use nix::sys::signal::{kill, Signal};
+use std::thread::sleep;
+use std::time::Duration;
+
+mod utils;
+use utils::*;
+
+/// Logs the reloads in `reload.log`, and the start of the service in `started.log`.
+const RELOADABLE_SCRIPT: &str = r#"#!/usr/bin/env bash
+echo started >> started.log
+trap 'echo HUP >> reload.log' HUP
+while true ; do
+    sleep 0.3
+done"#;
+
+fn test_reload(reload: &str, expected_log: &str) {
+    let (mut cmd, temp_dir) = get_cli();
+    store_service_script(temp_dir.path(), RELOADABLE_SCRIPT, Some(reload), Some("a"));
+    let recv = run_async(&mut cmd, true);
+    sleep(Duration::from_secs(2));
+    let socket_path = get_path(temp_dir.path(), recv.pid.as_raw());
+    let mut client = ClientHandler::new_client(&socket_path).unwrap();
+    let service_name = client.send_reload_request("a.toml".into()).unwrap();
+    assert_eq!(service_name, "a.toml");
+    sleep(Duration::from_secs(2));
+    let mut client = ClientHandler::new_client(&socket_path).unwrap();
+    let (_, status, _) = client.send_status_request("a.toml".into()).unwrap();
+    assert_eq!(status, HorustMsgServiceStatus::Running);
+    kill(recv.pid, Signal::SIGINT).expect("kill");
+    recv.recv_or_kill(Duration::from_secs(10));
+    let log = std::fs::read_to_string(temp_dir.path().join("reload.log")).unwrap();
+    assert_eq!(log, expected_log);
+    // It has been neither stopped nor restarted.
+    let started = std::fs::read_to_string(temp_dir.path().join("started.log")).unwrap();
+    assert_eq!(started, "started\n");
+}
+
+#[test]
+fn test_reload_signal() {
+    test_reload("[reload]\nsignal = \"HUP\"", "HUP\n");
+}
+
+#[test]
+fn test_reload_command() {
+    let reload = r#"[reload]
+command = ["/bin/sh", "-c", "echo command >> reload.log"]"#;
+    test_reload(reload, "command\n");
+}
+
+#[test]
+fn test_reload_not_defined() {
+    let (mut cmd, temp_dir) = get_cli();
+    store_service_script(temp_dir.path(), RELOADABLE_SCRIPT, None, Some("a"));
+    let recv = run_async(&mut cmd, true);
+    sleep(Duration::from_secs(2));
+    let socket_path = get_path(temp_dir.path(), recv.pid.as_raw());
+    let mut client = ClientHandler::new_client(&socket_path).unwrap();
+    let err = client.send_reload_request("a.toml".into()).unwrap_err();
+    assert!(err.to_string().contains("no reload signal"), "{}", err);
+    kill(recv.pid, Signal::SIGINT).expect("kill");
+    recv.recv_or_kill(Duration::from_secs(10));
+}
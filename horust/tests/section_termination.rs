@@ -1,4 +1,5 @@
 use nix::sys::signal::{kill, Signal};
+use nix::unistd::Pid;
 use std::time::Duration;
 
 pub mod utils;
@@ -119,3 +120,79 @@ exit 1
     let recv = run_async(&mut cmd, true);
     recv.recv_or_kill(Duration::from_secs(10));
 }
+
+/// Runs a service leaving a `sleep` in its process group, and returns whether it has survived.
+fn test_kill_mode(kill_mode: &str, main_exits: bool) -> bool {
+    let (mut cmd, temp_dir) = get_cli();
+    let pid_file = temp_dir.path().join("grandchild.pid");
+    let script = format!(
+        r#"#!/usr/bin/env bash
+sleep 30 &
+echo $! > {}
+{}
+"#,
+        pid_file.display(),
+        if main_exits { "exit 0" } else { "wait" }
+    );
+    let service = format!(
+        r#"[termination]
+wait = "10s"
+kill-mode = "{}""#,
+        kill_mode
+    );
+    store_service_script(temp_dir.path(), &script, Some(&service), None);
+
+    let recv = run_async(&mut cmd, true);
+    if !main_exits {
+        kill(recv.pid, Signal::SIGINT).expect("kill");
+    }
+    recv.recv_or_kill(Duration::from_secs(10));
+    let grandchild = std::fs::read_to_string(pid_file).unwrap();
+    let grandchild = Pid::from_raw(grandchild.trim().parse().unwrap());
+    let survived = kill(grandchild, None).is_ok();
+    if survived {
+        kill(grandchild, Signal::SIGKILL).unwrap();
+    }
+    survived
+}
+
+#[test]
+fn test_termination_kill_mode_process() {
+    assert!(test_kill_mode("process", false));
+}
+
+#[test]
+fn test_termination_kill_mode_process_group() {
+    assert!(!test_kill_mode("process-group", false));
+}
+
+#[test]
+fn test_termination_kill_mode_process_group_main_exited() {
+    assert!(!test_kill_mode("process-group", true));
+}
+
+#[test]
+fn test_termination_steps() {
+    let (mut cmd, temp_dir) = get_cli();
+    let signals_file = temp_dir.path().join("signals");
+    // INT is only recorded, TERM terminates the service.
+    let script = format!(
+        r#"#!/usr/bin/env bash
+trap 'echo INT >> {0}' INT
+trap 'echo TERM >> {0}; exit 0' TERM
+while true ; do
+    sleep 0.3
+done
+"#,
+        signals_file.display()
+    );
+    let service = r#"[termination]
+steps = [{ signal = "INT", wait = "1s" }, { signal = "TERM", wait = "10s" }]"#;
+    store_service_script(temp_dir.path(), &script, Some(service), None);
+
+    let recv = run_async(&mut cmd, true);
+    kill(recv.pid, Signal::SIGINT).expect("kill");
+    recv.recv_or_kill(Duration::from_secs(8));
+    let signals = std::fs::read_to_string(signals_file).unwrap();
+    assert_eq!(signals, "INT\nTERM\n");
+}
@@ -18,7 +18,8 @@ struct HorustctlArgs {
     uds_folder_path: PathBuf,
 
     // Specify the full path of the socket. It takes precedence other over arguments.
-    #[arg(long)]
+    // Services spawned by horust have it set in their environment.
+    #[arg(long, env = "HORUST_SOCKET_PATH")]
     socket_path: Option<PathBuf>,
 
     #[command(subcommand)]
@@ -30,6 +31,10 @@ enum Commands {
     Status(StatusArgs),
     Info(InfoArgs),
     Change(ChangeArgs),
+    /// Print the configuration the service is running with.
+    Config(ConfigArgs),
+    /// Ask the service to reload its configuration, without restarting it.
+    Reload(ReloadArgs),
 }
 
 #[derive(Args, Debug)]
@@ -42,6 +47,16 @@ struct InfoArgs {
     service_name: Option<String>,
 }
 
+#[derive(Args, Debug)]
+struct ConfigArgs {
+    service_name: String,
+}
+
+#[derive(Args, Debug)]
+struct ReloadArgs {
+    service_name: String,
+}
+
 #[derive(Args, Debug)]
 struct ChangeArgs {
     service_name: Option<String>,
@@ -66,12 +81,15 @@ fn main() -> Result<()> {
         Commands::Status(status_args) => {
             debug!("Status command received: {status_args:?}");
             debug!("uds path : {uds_path:?}");
-            let (service_name, service_status) =
+            let (service_name, service_status, reason) =
                 uds_handler.send_status_request(status_args.service_name.clone().unwrap())?;
             println!(
                 "Current status for '{service_name}' is: '{}'.",
                 service_status.as_str_name(),
             );
+            if let Some(reason) = reason {
+                println!("{reason}");
+            }
         },
         Commands::Info(info_args) => {
             debug!("Info command received: {info_args:?}");
@@ -82,6 +100,19 @@ fn main() -> Result<()> {
                 "Current Info for '{service_name}' is: '{}'.", service_info.as_str()
             );
         },
+        Commands::Config(config_args) => {
+            debug!("Config command received: {config_args:?}");
+            debug!("uds path : {uds_path:?}");
+            let (_service_name, config) =
+                uds_handler.send_config_request(config_args.service_name.clone())?;
+            print!("{config}");
+        }
+        Commands::Reload(reload_args) => {
+            debug!("Reload command received: {reload_args:?}");
+            debug!("uds path : {uds_path:?}");
+            let service_name = uds_handler.send_reload_request(reload_args.service_name.clone())?;
+            println!("Reload of '{service_name}' requested.");
+        }
         Commands::Change(change_args) => {
             debug!("Change command received: {change_args:?}");
             debug!("uds path : {uds_path:?}");
@@ -114,4 +114,17 @@ done"#,
         .assert()
         .success()
         .stdout(contains("running"));
+
+    let mut horustctl_cmd = Command::cargo_bin("horustctl").unwrap();
+    horustctl_cmd
+        .current_dir(&temp_dir)
+        .args(vec![
+            "--uds-folder-path",
+            temp_dir.path().display().to_string().as_str(),
+            "config",
+            "running.toml",
+        ])
+        .assert()
+        .success()
+        .stdout(contains("name = \"running.toml\""));
 }